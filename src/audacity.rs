@@ -0,0 +1,90 @@
+use std::io::prelude::*;
+
+use script::{Cue, ScriptError, parse_duration};
+
+/// Read an Audacity label track export into cues, each with the line of the
+/// label it came from.
+///
+/// Each line of the export is a label's start time and end time in seconds,
+/// then its text, separated by tabs. Every label becomes an `At` cue for its
+/// start time followed by a `Fire` cue for the channel or group names in its
+/// text, separated by spaces. End times are ignored, as are the frequency
+/// lines starting with `\` which Audacity adds for spectral selections.
+pub fn read_labels<B: BufRead>(bf: B) -> Result<Vec<(usize, Cue)>, Vec<ScriptError>> {
+    let mut cues: Vec<(usize, Cue)> = Vec::new();
+    let mut errors: Vec<ScriptError> = Vec::new();
+
+    for (lineno, line) in bf.lines().enumerate() {
+        let lineno = lineno + 1;
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                errors.push(ScriptError::from(e));
+                break;
+            },
+        };
+
+        if line.trim().is_empty() || line.starts_with('\\') {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.splitn(3, '\t').collect();
+        if fields.len() != 3 {
+            errors.push(ScriptError::Parse { lineno, error: "Label must have start, end and text" });
+            continue;
+        }
+
+        let time = match parse_duration(fields[0].trim()) {
+            Some(time) => time,
+            None => {
+                errors.push(ScriptError::Parse { lineno, error: "Invalid label time" });
+                continue;
+            },
+        };
+
+        let channels: Vec<String> = fields[2].split_whitespace().map(|s| s.to_string()).collect();
+        if channels.is_empty() {
+            errors.push(ScriptError::Parse { lineno, error: "Label has no channels to fire" });
+            continue;
+        }
+
+        cues.push((lineno, Cue::At { time }));
+        cues.push((lineno, Cue::Fire { channels }));
+    }
+
+    if errors.is_empty() {
+        Ok(cues)
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::read_labels;
+    use script::Cue;
+    use std::time::Duration;
+
+    #[test]
+    fn labels() {
+        let labels = "1.500000\t1.500000\tch1\n\\\t120.0\t3000.0\n62.25\t65.0\tch2 finale\n";
+        assert_eq!(read_labels(labels.as_bytes()).unwrap(), vec![
+            (1, Cue::At { time: Duration::from_millis(1500) }),
+            (1, Cue::Fire { channels: vec!["ch1".to_string()] }),
+            (3, Cue::At { time: Duration::from_millis(62250) }),
+            (3, Cue::Fire { channels: vec!["ch2".to_string(), "finale".to_string()] }),
+        ]);
+    }
+
+    #[test]
+    fn label_errors() {
+        let labels = "1.5\tch1\nsoon\t2.0\tch1\n3.0\t3.0\t \n";
+        let errors: Vec<String> = read_labels(labels.as_bytes()).unwrap_err()
+                                                                .iter()
+                                                                .map(|e| e.to_string())
+                                                                .collect();
+        assert_eq!(errors, vec!["line 1: Label must have start, end and text",
+                                "line 2: Invalid label time",
+                                "line 3: Label has no channels to fire"]);
+    }
+}
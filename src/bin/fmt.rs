@@ -0,0 +1,60 @@
+extern crate iris;
+use iris::{format, script};
+use iris::script::{Cue, Script};
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+
+/// The parts of a script which decide how the show runs, leaving out the
+/// line numbers things were defined on and the order of the definitions.
+fn show_of(script: &Script) -> impl PartialEq + '_ {
+    let cues: Vec<&Cue> = script.cues.iter().filter(|cue| !matches!(**cue,
+        Cue::Board { .. } | Cue::Channel { .. } | Cue::Group { .. } |
+        Cue::Threshold { .. } | Cue::Igniter { .. })).collect();
+    let thresholds: HashMap<_, _> = script.thresholds.iter()
+                                                     .map(|(&name, &(value, _))| (name, value))
+                                                     .collect();
+    let igniters: HashMap<_, _> = script.igniters.iter()
+                                                 .map(|(name, i)| (name, (i.min, i.max)))
+                                                 .collect();
+    (cues, &script.boards, &script.channels, &script.groups, &script.channel_info,
+     thresholds, igniters, &script.channel_igniters, script.duration)
+}
+
+fn main() {
+    let args: Vec<_> = env::args().collect();
+    if args.len() != 2 {
+        println!("Usage: {} <script file>", args[0]);
+        println!("Prints the script in canonical form, keeping its comments and the");
+        println!("files it includes.");
+        return;
+    }
+
+    let source = fs::read_to_string(&args[1]).unwrap_or_else(|e| {
+        println!("Could not read {}: {}", args[1], e);
+        process::exit(1);
+    });
+    let script = script::Script::from_file_all_errors(&args[1]).unwrap_or_else(|errors| {
+        for error in errors {
+            println!("{}", error.describe(&source));
+        }
+        process::exit(1);
+    });
+
+    // Moving definitions above the included files could change what they
+    // refer to, so check the formatted script runs the same show.
+    let text = format::format_source(&source);
+    let dir = Path::new(&args[1]).parent().unwrap_or_else(|| Path::new(""));
+    match Script::from_string_in_dir_all_errors(text.clone(), dir) {
+        Ok(ref formatted) if show_of(formatted) == show_of(&script) => print!("{}", text),
+        _ => {
+            println!("{} can't be formatted without changing the show, as its definitions",
+                     args[1]);
+            println!("depend on the order of the files it includes.");
+            process::exit(1);
+        },
+    }
+}
@@ -0,0 +1,40 @@
+extern crate iris;
+use iris::{format, json, script};
+
+use std::env;
+use std::fs;
+use std::process;
+
+fn main() {
+    let args: Vec<_> = env::args().collect();
+    if args.len() != 3 || (args[1] != "export" && args[1] != "import") {
+        println!("Usage: {} export <script file>", args[0]);
+        println!("       {} import <json file>", args[0]);
+        println!("Export prints a script as JSON. Import checks a JSON show and prints it");
+        println!("as a script.");
+        return;
+    }
+
+    let source = fs::read_to_string(&args[2]).unwrap_or_else(|e| {
+        println!("Could not read {}: {}", args[2], e);
+        process::exit(1);
+    });
+
+    if args[1] == "export" {
+        let script = script::Script::from_file_all_errors(&args[2]).unwrap_or_else(|errors| {
+            for error in errors {
+                println!("{}", error.describe(&source));
+            }
+            process::exit(1);
+        });
+        println!("{}", json::to_json(&script));
+    } else {
+        let script = json::from_json(&source).unwrap_or_else(|errors| {
+            for error in errors {
+                println!("{}", error);
+            }
+            process::exit(1);
+        });
+        print!("{}", format::format_script(&script));
+    }
+}
@@ -1,8 +1,12 @@
 extern crate iris;
-use iris::{script, portfire};
+use iris::{checks, lint, script, portfire, show};
 
 use std::env;
+use std::fs;
+use std::process;
+use std::time::Duration;
 use std::collections::HashMap;
+use std::sync::Arc;
 
 fn main() {
     let args: Vec<_> = env::args().collect();
@@ -12,104 +16,74 @@ fn main() {
     }
 
     println!("Reading script...");
-    let script = script::Script::from_file(&args[1]).unwrap();
+    let script = script::Script::from_file_all_errors(&args[1]).unwrap_or_else(|errors| {
+        let source = fs::read_to_string(&args[1]).unwrap_or_default();
+        for error in errors {
+            println!("{}", error.describe(&source));
+        }
+        process::exit(1);
+    });
     println!("    {} boards", script.boards.len());
     println!("    {} channels", script.channels.len());
     println!("    {} cues", script.cues.len());
-    println!("    {}s duration", script.duration);
+    println!("    {:.3}s duration", script.duration.as_secs_f64());
+    if script.sequencing > Duration::from_secs(0) {
+        println!("    {:.3}s of which is sequencing fires of more than three channels per board",
+                 script.sequencing.as_secs_f64());
+    }
+    let thresholds = checks::Thresholds::from_script(&script);
+    let mut set: Vec<(&checks::ThresholdName, &(f32, script::SourceLine))> =
+        script.thresholds.iter().collect();
+    set.sort_by_key(|&(_, (_, line))| line);
+    for (name, &(value, ref line)) in set {
+        println!("    threshold {} {} set on {}", name, value, line);
+    }
+    for warning in lint::lint(&script) {
+        println!("    WARNING: {}", warning);
+    }
 
     println!("Autodiscovering portfires...");
-    let mut discovered_portfires = portfire::autodiscover().unwrap();
+    let discovered_portfires = portfire::autodiscover().unwrap_or_else(|e| {
+        println!("    Could not discover boards: {}", e);
+        process::exit(1);
+    });
     println!("    Found {} boards, expected {}", discovered_portfires.len(), script.boards.len());
 
     println!("Matching boards to script...");
-    let mut portfires: HashMap<String, portfire::Board> = HashMap::new();
-    for (board_id, mac) in script.boards.iter() {
-        let mut board_found = false;
-        for portfire in &mut discovered_portfires {
-            if portfire.mac == *mac {
-                println!("    {} -> {}", board_id, portfire.ip);
-                portfires.insert(board_id.clone(), portfire.clone());
-                board_found = true;
-            }
-        }
-        if !board_found {
-            println!("Didn't find board {} {:2X}:{:2X}:{:2X}:{:2X}:{:2X}:{:2X}",
-                     board_id, mac[0], mac[1], mac[2], mac[3], mac[4], mac[5]);
-            return;
-        }
+    let (matched, missing) = show::match_boards(&script, &discovered_portfires);
+    for (board_id, board) in matched.iter() {
+        println!("    {} -> {}", board_id, board.ip);
     }
+    if let Some(board_id) = missing.first() {
+        let mac = script.boards[board_id];
+        println!("Didn't find board {} {:2X}:{:2X}:{:2X}:{:2X}:{:2X}:{:2X}",
+                 board_id, mac[0], mac[1], mac[2], mac[3], mac[4], mac[5]);
+        return;
+    }
+    let portfires: HashMap<String, Arc<portfire::Client>> =
+        matched.into_iter().map(|(id, board)| (id, Arc::new(board.client()))).collect();
 
-    println!("\nChecking boards individually...\n");
-    let mut got_error = false;
-
-    for (board_id, board) in portfires.iter() {
-        println!("");
-        println!("Board {} =========================", board_id);
-
-        board.ping().unwrap();
-        println!("Ping..........................OK");
-
-        let v = board.bus_voltage().unwrap();
-        print!("Bus voltage {:.<18.2}", v);
-        if v < 1.0 {
-            println!("OK");
-        } else {
-            println!("ERROR");
-            got_error = true;
-        }
-
-        let conts = board.continuities().unwrap();
-
-        // Check all assigned channels are connected
-        for (ch, &(ref ch_bid, ref ch_num)) in script.channels.iter() {
-            if ch_bid == board_id {
-                let ch_cont = conts[*ch_num as usize - 1];
-                print!("Channel #{:02} {: <12} ", ch_num, ch);
-                if ch_cont == 255 {
-                    println!("not connected, ERROR");
-                    got_error = true;
-                } else {
-                    println!("{: >3}Ω OK", ch_cont);
-                }
-            }
-        }
+    let _session = portfire::ArmedSession::new(portfires.values().cloned().collect());
 
-        // Check all unassigned channels are not connected
-        for num in 1..31 {
-            let mut channel_used = false;
-            for &(ref ch_bid, ref ch_num) in script.channels.values() {
-                if ch_bid == board_id && *ch_num == num {
-                    channel_used = true;
+    println!("\nChecking boards individually...\n");
+    let report = checks::check_boards(&script, &portfires, &thresholds,
+                                      checks::AfterChecks::Disarm);
+    for board in &report.boards {
+        println!();
+        println!("Board {} =========================", board.board_id);
+        for result in &board.results {
+            // Unused channels are only worth mentioning if connected
+            if let checks::Check::UnusedChannel { .. } = result.check {
+                if result.passed() {
+                    continue;
                 }
             }
-            if !channel_used && conts[num as usize - 1] != 255 {
-                println!("Unused channel #{:02} connected  ERROR", num);
-                got_error = true;
-            }
+            print_result(result);
         }
-
-        print!("Continuity voltage {:.<11.1}", conts[30] as f32 /10.0);
-        if conts[30] > 30 {
-            println!("OK");
-        } else {
-            println!("ERROR");
-            got_error = true;
-        }
-
-        board.arm().unwrap();
-        let v = board.bus_voltage().unwrap();
-        print!("Arm voltage {:.<18.2}", v);
-        if v > 2.5 {
-            println!("OK");
-        } else {
-            println!("ERROR");
-            got_error = true;
-        }
-        board.disarm().unwrap();
     }
+    let got_error = !report.passed();
 
-    println!("");
+    println!();
 
     if got_error {
         println!("An error occurred, quitting.");
@@ -117,3 +91,18 @@ fn main() {
         println!("No errors, good to go!");
     }
 }
+
+/// Print a check as a dotted line ending in OK, or ERROR and why.
+fn print_result(result: &checks::CheckResult) {
+    let label = match result.value_string() {
+        Some(value) => format!("{} {} ", result.check, value),
+        None => format!("{}", result.check),
+    };
+    if result.passed() {
+        println!("{:.<30}OK", label);
+    } else if let Some(ref error) = result.error {
+        println!("{:.<30}ERROR: {}", label, error);
+    } else if let Some(requirement) = result.requirement() {
+        println!("{:.<30}ERROR, should be {}", label, requirement);
+    }
+}
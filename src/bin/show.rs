@@ -2,10 +2,18 @@ use std::collections::HashMap;
 use std::thread;
 use std::time::Duration;
 use std::io;
+use std::io::prelude::*;
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver};
+use std::fs;
+use std::process;
 
 extern crate iris;
-use iris::portfire;
-use iris::script::{self, Cue};
+use iris::checks::{self, AfterChecks};
+use iris::lint;
+use iris::portfire::{self, FireOutcome};
+use iris::script::{self, Cue, format_timecode};
+use iris::show::{self, Command, Show, ShowEvent, ShowOptions, ShowState};
 
 extern crate clap;
 use clap::App;
@@ -13,6 +21,9 @@ use clap::App;
 #[cfg(feature="tts")]
 use iris::tts::TTS;
 
+const CONTROLS_HELP: &str = "Controls: Enter continues from a pause, h holds the show, \
+                             r resumes, x aborts and disarms every board";
+
 fn main() {
     let args = App::new("IRIS")
                     .args_from_usage("
@@ -29,89 +40,59 @@ fn main() {
     let skipsleep = args.is_present("skip-sleep");
 
     // Read script
-    let script = script::Script::from_file(&scriptpath).unwrap();
+    let script = script::Script::from_file_all_errors(scriptpath).unwrap_or_else(|errors| {
+        let source = fs::read_to_string(scriptpath).unwrap_or_default();
+        for error in errors {
+            println!("{}", error.describe(&source));
+        }
+        process::exit(1);
+    });
+    for warning in lint::lint(&script) {
+        println!("WARNING: {}", warning);
+    }
 
     // Find Portfires and map to script
-    let mut discovered_portfires = portfire::autodiscover().unwrap();
-    let mut portfires: HashMap<String, portfire::Board> = HashMap::new();
-    for (board_id, mac) in script.boards.iter() {
-        let mut board_found = false;
-        for portfire in &mut discovered_portfires {
-            if portfire.mac == *mac {
-                portfires.insert(board_id.clone(), portfire.clone());
-                board_found = true;
-            }
-        }
-        if !board_found && !skipchecks {
+    let discovered_portfires = portfire::autodiscover().unwrap_or_else(|e| {
+        println!("Could not discover boards: {}", e);
+        process::exit(1);
+    });
+    let (matched, missing) = show::match_boards(&script, &discovered_portfires);
+    if !skipchecks {
+        if let Some(board_id) = missing.first() {
+            let mac = script.boards[board_id];
             println!("Didn't find board {} {:2X}:{:2X}:{:2X}:{:2X}:{:2X}:{:2X}",
                      board_id, mac[0], mac[1], mac[2], mac[3], mac[4], mac[5]);
             return;
         }
     }
-
-    // Check all portfires are behaving and have correct continuities
-    let mut got_error = false;
-    for (board_id, board) in portfires.iter() {
-        // Check ping
-        board.ping().unwrap();
-
-        // Bus voltage while disarmed should be 0
-        let v = board.bus_voltage().unwrap();
-        if v > 1.0 {
-            println!("Board {} bus voltage {}v ERROR", board_id, v);
-            got_error = true;
-        }
-
-        // Check all continuities are correct
-        let conts = board.continuities().unwrap();
-        for (ch, &(ref ch_bid, ref ch_num)) in script.channels.iter() {
-            if ch_bid == board_id {
-                let ch_cont = conts[*ch_num as usize - 1];
-                if ch_cont == 255 {
-                    println!("Board {} ch#{} '{}' not connected, ERROR",
-                             board_id, ch_num, ch);
-                    got_error = true;
-                }
-            }
-        }
-
-        // Check all unassigned channels are not connected
-        for num in 1..31 {
-            let mut channel_used = false;
-            for &(ref ch_bid, ref ch_num) in script.channels.values() {
-                if ch_bid == board_id && *ch_num == num {
-                    channel_used = true;
-                }
-            }
-            if !channel_used && conts[num as usize - 1] != 255 {
-                println!("Board {} unused channel #{:02} connected, ERROR",
-                         board_id, num);
-                got_error = true;
-            }
-        }
-
-        // Check the continuity test voltage is not pulled down
-        if conts[30] < 30 {
-            println!("Board {} continuity voltage {}, ERROR",
-                     board_id, conts[30]);
-            got_error = true;
-        }
-
-        // Check the boards arm and the bus voltage comes up
-        board.arm().unwrap();
-        let v = board.bus_voltage().unwrap();
-        if v < 2.5 {
-            println!("Board {} arm voltage {}, ERROR", board_id, v);
-            got_error = true;
+    let portfires: HashMap<String, Arc<portfire::Client>> =
+        matched.into_iter().map(|(id, board)| (id, Arc::new(board.client()))).collect();
+
+    // From here on every board is disarmed when the show is dropped, even on
+    // a panic or Ctrl-C
+    let options = ShowOptions {
+        dry_run: dryrun,
+        skip_sleep: skipsleep,
+        // Give up on a board after a few seconds rather than holding up the show
+        retry_policy: portfire::RetryPolicy::default(),
+    };
+    let mut show = Show::new(script, portfires, read_commands(), options);
+
+    // Check all portfires are behaving and have correct continuities, leaving
+    // them armed for the show
+    let thresholds = checks::Thresholds::from_script(show.script());
+    let report = checks::check_boards(show.script(), show.clients(), &thresholds,
+                                      AfterChecks::StayArmed);
+    for board in &report.boards {
+        for failure in board.failures() {
+            println!("Board {} {}, ERROR", board.board_id, failure);
         }
     }
+    let got_error = !report.passed();
 
     // Quit early if anything went wrong in setup
     if got_error && !skipchecks {
         println!("An error occurred, disarming and quitting.");
-        for board in portfires.values() {
-            board.disarm().unwrap();
-        }
         return;
     }
 
@@ -119,66 +100,112 @@ fn main() {
     #[cfg(feature="tts")]
     let tts = TTS::new();
 
+    println!("{}", CONTROLS_HELP);
+
     // Run the show!
-    for cue in script.cues {
-        match cue {
-            Cue::Sleep { time } => {
+    let mut current_cue = String::new();
+    let state = show.run(|event| match event {
+        ShowEvent::Cue { cue, planned, lateness, .. } => match cue {
+            Cue::Fire { .. } => current_cue = cue.to_string(),
+            Cue::Print { ref message } => {
                 if !skipsleep {
-                    thread::sleep(Duration::from_secs(time));
+                    report_time(planned, lateness, &cue.to_string());
                 }
-            },
-
-            Cue::Pause => {
-                let mut l = String::new();
-                let _ = io::stdin().read_line(&mut l);
-            },
-
-            Cue::Print { message } => {
                 println!("{}", message);
             },
-
-            Cue::Say { message } => {
+            Cue::Say { ref message } => {
+                if !skipsleep {
+                    report_time(planned, lateness, &cue.to_string());
+                }
                 #[cfg(feature="tts")]
-                tts.say(&message);
+                tts.say(message);
                 #[cfg(not(feature="tts"))]
                 println!("SAYING: {}", message);
             },
-
-            Cue::Fire { channels } => {
-                // Accumulate numerical channels to fire on each board
-                let mut board_channels: HashMap<String, Vec<u8>> = HashMap::new();
-                for channel in channels {
-                    let (ref board_id, ref ch_num) = script.channels[&channel];
-                    let chs = board_channels.entry(board_id.clone())
-                                            .or_insert(Vec::new());
-                    (*chs).push(*ch_num);
-                }
-
-                // Send the fire commands
-                for (ref board_id, ref chans) in board_channels.iter() {
-                    let mut firing_chans = [0u8; 3];
-                    for (idx, chan) in chans.iter().enumerate() {
-                        firing_chans[idx] = *chan;
-                    }
-                    if !dryrun {
-                        let portfire = &portfires[&board_id.to_string()];
-                        portfire.fire_retry(firing_chans);
-                    } else {
-                        println!("FIRING Board {} Channels {:?}", board_id, firing_chans);
-                    }
+            _ => {},
+        },
+        ShowEvent::Paused => println!("PAUSED, Enter to continue"),
+        ShowEvent::Held => println!("HOLDING, r to resume or x to abort"),
+        ShowEvent::Resumed => println!("RESUMED"),
+        ShowEvent::FireStep { planned, lateness, boards } => {
+            if !skipsleep {
+                report_time(planned, lateness, &current_cue);
+            }
+            if dryrun {
+                for (board_id, firing_chans) in boards {
+                    println!("FIRING Board {} Channels {:?}", board_id, firing_chans);
                 }
+            }
+        },
+        ShowEvent::Fired { board_id, channels, outcome } => match outcome {
+            FireOutcome::Fired => {},
+            FireOutcome::Late { attempts, late } => {
+                println!("WARNING: Board {} Channels {:?} fired {}ms late after {} attempts",
+                         board_id, channels, late.as_millis(), attempts);
+            },
+            FireOutcome::Failed { attempts, error } => {
+                println!("ERROR: Board {} Channels {:?} failed to fire after {} attempts: {}",
+                         board_id, channels, attempts, error);
             },
+        },
+        ShowEvent::Skew { boards, skew } =>
+            println!("Fired {} boards with {:.1}ms skew", boards, skew.as_secs_f64() * 1000.0),
+        ShowEvent::Aborted { disarmed } => {
+            for (board, result) in disarmed {
+                if let Err(e) = result {
+                    println!("{} disarm failed: {}", board, e);
+                }
+            }
+            println!("ABORTED, all boards disarmed");
+        },
+    });
+
+    if state == ShowState::Aborted {
+        // The boards are already disarmed, and exiting skips dropping the show.
+        let (fired, unfired) = (show.fired(), show.unfired());
+        println!("Fired {} channels: {}", fired.len(), fired.join(" "));
+        println!("Not fired {} channels: {}", unfired.len(), unfired.join(" "));
+        process::exit(1);
+    }
 
-            _ => {},
-        }
+    if let Some((lateness, planned)) = show.latest() {
+        println!("Largest lateness {:+.1}ms, for the cue planned at {}", lateness * 1000.0,
+                 format_timecode(planned));
     }
 
     // Wait for final user input before quitting, in case of pending TTS
-    let mut l = String::new();
-    let _ = io::stdin().read_line(&mut l);
+    println!("Show complete, Enter to disarm and quit");
+    show.pause();
 
-    // Show over, disarm
-    for board in portfires.values() {
-        let _ = board.disarm();
-    }
+    // Show over, every board is disarmed as the show is dropped
+}
+
+/// Read operator commands from stdin on a separate thread, so that they can
+/// be acted on while the show is waiting.
+fn read_commands() -> Receiver<Command> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let command = match line.as_ref().map(|l| l.trim()) {
+                Ok("") => Command::Continue,
+                Ok("h") | Ok("hold") => Command::Hold,
+                Ok("r") | Ok("resume") => Command::Resume,
+                Ok("x") | Ok("abort") => Command::Abort,
+                Ok(_) => {
+                    println!("{}", CONTROLS_HELP);
+                    continue;
+                },
+                Err(_) => break,
+            };
+            if tx.send(command).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+/// Print the planned show time of a cue and how late it actually ran.
+fn report_time(planned: Duration, lateness: f64, cue: &str) {
+    println!("{} {:+7.1}ms  {}", format_timecode(planned), lateness * 1000.0, cue);
 }
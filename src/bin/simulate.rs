@@ -0,0 +1,83 @@
+extern crate iris;
+use iris::script;
+use iris::simulator::{SimBoard, SimEvent};
+
+use std::env;
+use std::fs;
+use std::process;
+use std::sync::mpsc;
+use std::thread;
+use std::net::Ipv4Addr;
+
+/// Address of the first simulated board. Any 127.x.x.x address can be used on
+/// Linux, but other systems only answer on the ones given to the loopback
+/// interface.
+const FIRST_ADDRESS: Ipv4Addr = Ipv4Addr::new(127, 0, 10, 1);
+
+fn main() {
+    let args: Vec<_> = env::args().collect();
+    if args.len() != 2 && args.len() != 3 {
+        println!("Usage: {} <script file> [first address]", args[0]);
+        println!("Boards are simulated at consecutive addresses from {}. Other than on",
+                 FIRST_ADDRESS);
+        println!("Linux, each must first be added to the loopback interface.");
+        return;
+    }
+    let first = match args.get(2) {
+        Some(address) => address.parse().unwrap_or_else(|_| {
+            println!("Invalid address '{}'", address);
+            process::exit(1);
+        }),
+        None => FIRST_ADDRESS,
+    };
+
+    println!("Reading script...");
+    let script = script::Script::from_file_all_errors(&args[1]).unwrap_or_else(|errors| {
+        let source = fs::read_to_string(&args[1]).unwrap_or_default();
+        for error in errors {
+            println!("{}", error.describe(&source));
+        }
+        process::exit(1);
+    });
+
+    // Start one simulated board per board in the script, with every channel
+    // the script uses connected.
+    println!("Starting simulated boards...");
+    let mut board_ids: Vec<&String> = script.boards.keys().collect();
+    board_ids.sort();
+    let (tx, rx) = mpsc::channel();
+    let mut sims: Vec<SimBoard> = Vec::new();
+    for (idx, board_id) in board_ids.iter().enumerate() {
+        let ip = Ipv4Addr::from(u32::from(first).wrapping_add(idx as u32));
+        let sim = SimBoard::start(ip, script.boards[*board_id]).unwrap_or_else(|e| {
+            println!("Could not start board {} on {}: {}", board_id, ip, e);
+            process::exit(1);
+        });
+        for &(ref ch_bid, ch_num) in script.channels.values() {
+            if ch_bid == *board_id {
+                sim.set_continuity(ch_num, 2);
+            }
+        }
+        println!("    {} -> {}", board_id, sim.board());
+
+        // Gather every board's events into one channel, tagged with its ID.
+        let events = sim.events();
+        let tx = tx.clone();
+        let board_id = board_id.to_string();
+        thread::spawn(move || for event in events {
+            if tx.send((board_id.clone(), event)).is_err() {
+                break;
+            }
+        });
+        sims.push(sim);
+    }
+
+    // Report every arm, disarm and fire as it happens.
+    for (board_id, event) in rx {
+        match event {
+            SimEvent::Armed => println!("Board {} ARMED", board_id),
+            SimEvent::Disarmed => println!("Board {} disarmed", board_id),
+            SimEvent::Fired(chans) => println!("Board {} FIRED channels {:?}", board_id, chans),
+        }
+    }
+}
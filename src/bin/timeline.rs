@@ -0,0 +1,77 @@
+extern crate iris;
+use iris::script::{self, Cue, SourceLine, format_timecode};
+use iris::timeline::Timeline;
+
+use std::env;
+use std::fs;
+use std::process;
+
+fn main() {
+    let args: Vec<_> = env::args().collect();
+    if args.len() != 2 {
+        println!("Usage: {} <script file>", args[0]);
+        return;
+    }
+
+    let script = script::Script::from_file_all_errors(&args[1]).unwrap_or_else(|errors| {
+        let source = fs::read_to_string(&args[1]).unwrap_or_default();
+        for error in errors {
+            println!("{}", error.describe(&source));
+        }
+        process::exit(1);
+    });
+    let timeline = Timeline::new(&script);
+
+    // Definitions take no show time, so only the other cues are listed.
+    let entries: Vec<_> = timeline.entries.iter().filter(|e| !matches!(*e.cue,
+        Cue::Board { .. } | Cue::Channel { .. } | Cue::Group { .. } |
+        Cue::Threshold { .. } | Cue::Igniter { .. })).collect();
+
+    // Lines from other files are shown with the file they are in.
+    let lines: Vec<String> = entries.iter().map(|e| line_column(e.line)).collect();
+    let width = lines.iter().map(|l| l.len()).max().unwrap_or(0).max(5);
+
+    println!("{:<12} {:>width$}  Cue", "Show time", "Line", width = width);
+    let mut pauses = 0;
+    for (entry, line) in entries.iter().zip(&lines) {
+        if entry.pauses > pauses {
+            pauses = entry.pauses;
+            println!("---- after pause {}, times depend on when the show is continued ----",
+                     pauses);
+        }
+
+        println!("{:<12} {:>width$}  {}", format_timecode(entry.time), line, entry.cue,
+                 width = width);
+        for fire in &entry.fires {
+            let channels: Vec<String> = fire.channels.iter().map(|c| c.to_string()).collect();
+            println!("{:<12} {:>width$}      board {} channels {}", format_timecode(fire.time),
+                     "", fire.board_id, channels.join(" "), width = width);
+        }
+    }
+
+    println!();
+    println!("Duration {}, of which {:.3}s is sequencing", format_timecode(script.duration),
+             script.sequencing.as_secs_f64());
+    if pauses > 0 {
+        println!("{} pause(s), so the total running time is not known", pauses);
+    }
+
+    println!();
+    println!("Fires per board:");
+    for (board_id, fires) in &timeline.boards {
+        if fires.commands == 0 {
+            println!("    {}: never fired", board_id);
+        } else {
+            println!("    {}: {} channels in {} fire commands", board_id, fires.channels,
+                     fires.commands);
+        }
+    }
+}
+
+/// The line number of a cue, preceded by its file if not the script itself.
+fn line_column(line: &SourceLine) -> String {
+    match line.path {
+        Some(ref path) => format!("{}:{}", path.display(), line.lineno),
+        None => line.lineno.to_string(),
+    }
+}
@@ -1,7 +1,11 @@
 extern crate iris;
 
+use iris::checks;
 use iris::portfire;
 
+use std::process;
+use std::sync::Arc;
+
 #[cfg(feature="tts")]
 use iris::tts;
 
@@ -18,7 +22,11 @@ fn main() {
     tts::init();
 
     say("Discovering portfires");
-    let boards = portfire::autodiscover().unwrap();
+    let boards = portfire::autodiscover().unwrap_or_else(|e| {
+        println!("Could not discover boards: {}", e);
+        say("Discovery failed");
+        process::exit(1);
+    });
 
     match boards.len() {
         0 => { say("No boards found"); return; },
@@ -26,7 +34,11 @@ fn main() {
         i => say(&format!("{} boards found", i)),
     }
 
-    for board in boards {
+    let session = portfire::ArmedSession::new(boards.iter()
+                                                    .map(|b| Arc::new(b.client()))
+                                                    .collect());
+
+    for board in session.clients() {
 
         say("Pinging");
         match board.ping() {
@@ -41,16 +53,21 @@ fn main() {
         }
 
         say("Checking continuities");
-        let conts = board.continuities().unwrap();
-        let channels: Vec<String> = conts.iter()
-                                        .enumerate()
-                                        .filter(|&(_, cont)| *cont != 255)
-                                        .map(|(idx, _)| (idx+1).to_string())
-                                        .collect();
-        if channels.len() == 1 {
-            say("No channels connected");
-        } else {
-            say(&format!("channels {} connected", channels.join(",")));
+        match board.continuities() {
+            Ok(conts) => {
+                let channels: Vec<String> = conts.iter()
+                                                .take(checks::CHANNELS as usize)
+                                                .enumerate()
+                                                .filter(|&(_, &cont)| cont != checks::OPEN)
+                                                .map(|(idx, _)| (idx+1).to_string())
+                                                .collect();
+                if channels.is_empty() {
+                    say("No channels connected");
+                } else {
+                    say(&format!("channels {} connected", channels.join(",")));
+                }
+            },
+            Err(_) => say("Error"),
         }
 
         say("Arming");
@@ -0,0 +1,567 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use portfire::{Client, PortfireError};
+use script::{Script, SourceLine};
+
+use serde::{Deserialize, Serialize};
+
+/// Continuity reading for a channel with nothing connected.
+pub const OPEN: u8 = 255;
+
+/// Number of channels on a board.
+pub const CHANNELS: u8 = 30;
+
+/// A threshold which a script can set with a `threshold` directive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThresholdName {
+    Disarmed,
+    Armed,
+    Continuity,
+    Open,
+}
+
+impl ThresholdName {
+    pub fn parse(name: &str) -> Option<ThresholdName> {
+        match name {
+            "disarmed" => Some(ThresholdName::Disarmed),
+            "armed" => Some(ThresholdName::Armed),
+            "continuity" => Some(ThresholdName::Continuity),
+            "open" => Some(ThresholdName::Open),
+            _ => None,
+        }
+    }
+
+    /// Whether `value` makes sense for this threshold: a voltage, or for
+    /// `Open` a continuity reading.
+    pub fn is_valid(&self, value: f32) -> bool {
+        match *self {
+            ThresholdName::Open => value >= 1.0 && value <= OPEN as f32 && value.fract() == 0.0,
+            _ => value.is_finite() && value >= 0.0,
+        }
+    }
+}
+
+impl fmt::Display for ThresholdName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ThresholdName::Disarmed => write!(f, "disarmed"),
+            ThresholdName::Armed => write!(f, "armed"),
+            ThresholdName::Continuity => write!(f, "continuity"),
+            ThresholdName::Open => write!(f, "open"),
+        }
+    }
+}
+
+/// Where the threshold a check used came from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ThresholdSource {
+    Default,
+    /// A `threshold` directive, on this line.
+    Script { line: SourceLine },
+    /// The type of igniter given for a channel, defined on this line.
+    Igniter { name: String, line: SourceLine },
+}
+
+impl fmt::Display for ThresholdSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ThresholdSource::Default => write!(f, "default"),
+            ThresholdSource::Script { ref line } => write!(f, "set on {}", line),
+            ThresholdSource::Igniter { ref name, ref line } =>
+                write!(f, "igniter '{}' defined on {}", name, line),
+        }
+    }
+}
+
+/// Limits the values read from a board must be within to pass.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Thresholds {
+    /// Bus voltage while disarmed must be below this.
+    pub disarmed_voltage: f32,
+    /// Bus voltage once armed must be above this.
+    pub armed_voltage: f32,
+    /// The continuity test voltage must be above this.
+    pub continuity_voltage: f32,
+    /// Continuity readings of this or more count as nothing connected.
+    pub open: u8,
+    /// The script line each threshold not left at its default was set on.
+    pub lines: HashMap<ThresholdName, SourceLine>,
+}
+
+impl Default for Thresholds {
+    fn default() -> Thresholds {
+        Thresholds {
+            disarmed_voltage: 1.0,
+            armed_voltage: 2.5,
+            continuity_voltage: 3.0,
+            open: OPEN,
+            lines: HashMap::new(),
+        }
+    }
+}
+
+impl Thresholds {
+    /// The defaults, with any set by `threshold` directives in `script`.
+    pub fn from_script(script: &Script) -> Thresholds {
+        let mut thresholds = Thresholds::default();
+        for (&name, &(value, ref line)) in script.thresholds.iter() {
+            thresholds.set(name, value, line.clone());
+        }
+        thresholds
+    }
+
+    /// Set a threshold, from a directive on `line`.
+    pub fn set(&mut self, name: ThresholdName, value: f32, line: SourceLine) {
+        match name {
+            ThresholdName::Disarmed => self.disarmed_voltage = value,
+            ThresholdName::Armed => self.armed_voltage = value,
+            ThresholdName::Continuity => self.continuity_voltage = value,
+            ThresholdName::Open => self.open = value as u8,
+        }
+        self.lines.insert(name, line);
+    }
+
+    pub fn source(&self, name: ThresholdName) -> ThresholdSource {
+        match self.lines.get(&name) {
+            Some(line) => ThresholdSource::Script { line: line.clone() },
+            None => ThresholdSource::Default,
+        }
+    }
+}
+
+/// A value a check passes for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Threshold {
+    Below(f32),
+    Above(f32),
+    /// A continuity reading below the given open reading, showing something
+    /// connected.
+    Connected(u8),
+    /// A continuity reading of at least the given open reading, showing
+    /// nothing connected.
+    Open(u8),
+    /// A continuity reading from `min` to `max`, showing the expected
+    /// igniter connected, and below `open`.
+    Range { min: u8, max: u8, open: u8 },
+}
+
+impl Threshold {
+    pub fn passes(&self, value: f32) -> bool {
+        match *self {
+            Threshold::Below(limit) => value < limit,
+            Threshold::Above(limit) => value > limit,
+            Threshold::Connected(open) => value < open as f32,
+            Threshold::Open(open) => value >= open as f32,
+            Threshold::Range { min, max, open } =>
+                value >= min as f32 && value <= max as f32 && value < open as f32,
+        }
+    }
+}
+
+impl fmt::Display for Threshold {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Threshold::Below(limit) => write!(f, "below {:.2}V", limit),
+            Threshold::Above(limit) => write!(f, "above {:.2}V", limit),
+            Threshold::Connected(open) => write!(f, "connected, reading below {}", open),
+            Threshold::Open(open) => write!(f, "open, reading {} or more", open),
+            Threshold::Range { min, max, .. } => write!(f, "between {}Ω and {}Ω", min, max),
+        }
+    }
+}
+
+/// Something checked on a board, in the order they are run.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Check {
+    Ping,
+    /// Bus voltage while disarmed.
+    BusVoltage,
+    Continuities,
+    /// A channel the script uses, which should have an igniter connected.
+    Channel { num: u8, name: String },
+    /// A channel the script doesn't use, which should have nothing connected.
+    UnusedChannel { num: u8 },
+    /// The voltage continuities are tested with, which drops if something
+    /// is pulling it down.
+    ContinuityVoltage,
+    Arm,
+    /// Bus voltage once armed.
+    ArmVoltage,
+    Disarm,
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Check::Ping => write!(f, "Ping"),
+            Check::BusVoltage => write!(f, "Bus voltage"),
+            Check::Continuities => write!(f, "Continuities"),
+            Check::Channel { num, ref name } => write!(f, "Channel #{:02} {}", num, name),
+            Check::UnusedChannel { num } => write!(f, "Unused channel #{:02}", num),
+            Check::ContinuityVoltage => write!(f, "Continuity voltage"),
+            Check::Arm => write!(f, "Arm"),
+            Check::ArmVoltage => write!(f, "Arm voltage"),
+            Check::Disarm => write!(f, "Disarm"),
+        }
+    }
+}
+
+/// The result of one check.
+#[derive(Debug)]
+pub struct CheckResult {
+    pub check: Check,
+    /// The value read, for checks which read one. Continuities are the raw
+    /// reading, with `OPEN` for nothing connected, and voltages are in volts.
+    pub value: Option<f32>,
+    pub threshold: Option<Threshold>,
+    /// Where the threshold came from.
+    pub source: Option<ThresholdSource>,
+    /// Set if the board didn't respond as expected.
+    pub error: Option<PortfireError>,
+}
+
+impl CheckResult {
+    fn ok(check: Check) -> CheckResult {
+        CheckResult { check, value: None, threshold: None, source: None, error: None }
+    }
+
+    fn error(check: Check, error: PortfireError) -> CheckResult {
+        CheckResult { check, value: None, threshold: None, source: None, error: Some(error) }
+    }
+
+    fn value(check: Check, value: f32, threshold: Threshold, source: ThresholdSource)
+        -> CheckResult
+    {
+        CheckResult {
+            check, value: Some(value), threshold: Some(threshold), source: Some(source),
+            error: None
+        }
+    }
+
+    pub fn passed(&self) -> bool {
+        match (self.value, self.threshold) {
+            _ if self.error.is_some() => false,
+            (Some(value), Some(threshold)) => threshold.passes(value),
+            _ => true,
+        }
+    }
+
+    /// The value read, with units, if any.
+    pub fn value_string(&self) -> Option<String> {
+        self.value.map(|value| match self.threshold {
+            Some(Threshold::Connected(open)) | Some(Threshold::Open(open)) |
+            Some(Threshold::Range { open, .. }) if value >= open as f32 => "open".to_string(),
+            Some(Threshold::Connected(_)) | Some(Threshold::Open(_)) |
+            Some(Threshold::Range { .. }) => format!("{}Ω", value),
+            _ => format!("{:.2}V", value),
+        })
+    }
+
+    /// What the value should have been and which threshold that came from,
+    /// for checks which read a value.
+    pub fn requirement(&self) -> Option<String> {
+        match (self.threshold, self.source.as_ref()) {
+            (Some(threshold), Some(source)) => Some(format!("{} ({})", threshold, source)),
+            (Some(threshold), None) => Some(threshold.to_string()),
+            _ => None,
+        }
+    }
+}
+
+/// Displayed as the check, any value read and why it failed.
+impl fmt::Display for CheckResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.check)?;
+        if let Some(value) = self.value_string() {
+            write!(f, " {}", value)?;
+        }
+        if let Some(ref error) = self.error {
+            write!(f, ": {}", error)?;
+        } else if let Some(requirement) = self.requirement() {
+            if !self.passed() {
+                write!(f, ", should be {}", requirement)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Every check run on one board. Checks after one the others depend on
+/// failing, such as ping or arm, are not run.
+#[derive(Debug)]
+pub struct BoardReport {
+    pub board_id: String,
+    pub results: Vec<CheckResult>,
+}
+
+impl BoardReport {
+    pub fn passed(&self) -> bool {
+        self.results.iter().all(|r| r.passed())
+    }
+
+    pub fn failures(&self) -> Vec<&CheckResult> {
+        self.results.iter().filter(|r| !r.passed()).collect()
+    }
+}
+
+/// Checks for every board, sorted by board ID.
+#[derive(Debug)]
+pub struct CheckReport {
+    pub boards: Vec<BoardReport>,
+}
+
+impl CheckReport {
+    pub fn passed(&self) -> bool {
+        self.boards.iter().all(|b| b.passed())
+    }
+}
+
+/// Whether to leave boards armed once checked, ready for the show.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AfterChecks {
+    Disarm,
+    StayArmed,
+}
+
+/// Check every board in `clients`, keyed by board ID, is behaving and has
+/// only the channels `script` uses connected.
+pub fn check_boards(script: &Script, clients: &HashMap<String, Arc<Client>>,
+                    thresholds: &Thresholds, after: AfterChecks) -> CheckReport {
+    let mut board_ids: Vec<&String> = clients.keys().collect();
+    board_ids.sort();
+    let boards = board_ids.into_iter()
+        .map(|board_id| check_board(script, board_id, &clients[board_id], thresholds, after))
+        .collect();
+    CheckReport { boards }
+}
+
+/// Check one board, as `check_boards`.
+pub fn check_board(script: &Script, board_id: &str, client: &Client, thresholds: &Thresholds,
+                   after: AfterChecks) -> BoardReport {
+    let mut report = BoardReport { board_id: board_id.to_string(), results: Vec::new() };
+    run_checks(script, board_id, client, thresholds, after, &mut report.results);
+    report
+}
+
+fn run_checks(script: &Script, board_id: &str, client: &Client, thresholds: &Thresholds,
+              after: AfterChecks, results: &mut Vec<CheckResult>) {
+    match client.ping() {
+        Ok(()) => results.push(CheckResult::ok(Check::Ping)),
+        Err(e) => return results.push(CheckResult::error(Check::Ping, e)),
+    }
+
+    results.push(match client.bus_voltage() {
+        Ok(v) => CheckResult::value(Check::BusVoltage, v,
+                                    Threshold::Below(thresholds.disarmed_voltage),
+                                    thresholds.source(ThresholdName::Disarmed)),
+        Err(e) => CheckResult::error(Check::BusVoltage, e),
+    });
+
+    match client.continuities() {
+        Ok(conts) => {
+            let mut used: Vec<(u8, &String)> = script.channels.iter()
+                .filter(|&(_, (b, _))| b == board_id)
+                .map(|(name, &(_, num))| (num, name))
+                .collect();
+            used.sort();
+            let open = thresholds.source(ThresholdName::Open);
+            for num in 1..CHANNELS + 1 {
+                let value = conts[num as usize - 1] as f32;
+                let channels: Vec<&String> =
+                    used.iter().filter(|&&(n, _)| n == num).map(|&(_, name)| name).collect();
+                if channels.is_empty() {
+                    results.push(CheckResult::value(Check::UnusedChannel { num }, value,
+                                                    Threshold::Open(thresholds.open),
+                                                    open.clone()));
+                }
+
+                // Channels with an igniter type given must read in its range,
+                // others just need something connected.
+                for name in channels {
+                    let igniter = script.channel_igniters.get(name)
+                                                         .map(|i| (i, &script.igniters[i]));
+                    let (threshold, source) = match igniter {
+                        Some((igniter, range)) => (
+                            Threshold::Range { min: range.min, max: range.max,
+                                               open: thresholds.open },
+                            ThresholdSource::Igniter { name: igniter.clone(),
+                                                       line: range.line.clone() }
+                        ),
+                        None => (Threshold::Connected(thresholds.open), open.clone()),
+                    };
+                    results.push(CheckResult::value(Check::Channel { num, name: name.clone() },
+                                                    value, threshold, source));
+                }
+            }
+            results.push(CheckResult::value(Check::ContinuityVoltage, conts[30] as f32 / 10.0,
+                                            Threshold::Above(thresholds.continuity_voltage),
+                                            thresholds.source(ThresholdName::Continuity)));
+        },
+        Err(e) => return results.push(CheckResult::error(Check::Continuities, e)),
+    }
+
+    match client.arm() {
+        Ok(()) => results.push(CheckResult::ok(Check::Arm)),
+        Err(e) => return results.push(CheckResult::error(Check::Arm, e)),
+    }
+    results.push(match client.bus_voltage() {
+        Ok(v) => CheckResult::value(Check::ArmVoltage, v,
+                                    Threshold::Above(thresholds.armed_voltage),
+                                    thresholds.source(ThresholdName::Armed)),
+        Err(e) => CheckResult::error(Check::ArmVoltage, e),
+    });
+
+    if after == AfterChecks::Disarm {
+        results.push(match client.disarm() {
+            Ok(()) => CheckResult::ok(Check::Disarm),
+            Err(e) => CheckResult::error(Check::Disarm, e),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_boards, AfterChecks, Check, Threshold, ThresholdName, ThresholdSource,
+                Thresholds};
+    use portfire::Board;
+    use script::{Script, SourceLine};
+    use simulator::SimBoard;
+    use std::collections::HashMap;
+    use std::net::Ipv4Addr;
+    use std::sync::Arc;
+
+    fn script() -> Script {
+        Script::from_string("
+board 001 00:00:00:00:01:20
+board 002 00:00:00:00:01:22
+channel a 001 1
+channel b 001 2
+channel c 002 1
+fire a b c
+".to_string()).unwrap()
+    }
+
+    #[test]
+    fn thresholds() {
+        assert!(Threshold::Below(1.0).passes(0.5));
+        assert!(!Threshold::Below(1.0).passes(1.0));
+        assert!(Threshold::Above(2.5).passes(12.0));
+        assert!(Threshold::Connected(255).passes(20.0));
+        assert!(!Threshold::Connected(255).passes(255.0));
+        assert!(Threshold::Open(255).passes(255.0));
+        assert!(Threshold::Open(200).passes(210.0));
+        let range = Threshold::Range { min: 1, max: 5, open: 255 };
+        assert!(range.passes(1.0) && range.passes(5.0));
+        assert!(!range.passes(0.0) && !range.passes(6.0) && !range.passes(255.0));
+    }
+
+    #[test]
+    fn checks() {
+        let sim = SimBoard::start(Ipv4Addr::new(127, 0, 1, 20), [0, 0, 0, 0, 1, 0x20]).unwrap();
+        let sim2 = SimBoard::start(Ipv4Addr::new(127, 0, 1, 21), [0, 0, 0, 0, 1, 0x21]).unwrap();
+        let dead = Board { ip: Ipv4Addr::new(127, 0, 1, 22), mac: [0, 0, 0, 0, 1, 0x22] };
+        sim.set_continuity(1, 12);
+        sim.set_continuity(2, 14);
+        let mut clients = HashMap::new();
+        clients.insert("001".to_string(), Arc::new(sim.board().client()));
+        clients.insert("002".to_string(), Arc::new(dead.client()));
+
+        let report = check_boards(&script(), &clients, &Thresholds::default(), AfterChecks::Disarm);
+        assert!(!report.passed());
+        assert_eq!(report.boards[0].board_id, "001");
+        assert!(report.boards[0].passed());
+        assert!(!sim.state().armed);
+        let checks: Vec<&Check> = report.boards[0].results.iter().map(|r| &r.check).collect();
+        assert_eq!(checks.len(), 36);
+        assert_eq!(*checks[2], Check::Channel { num: 1, name: "a".to_string() });
+        assert_eq!(report.boards[0].results[2].to_string(), "Channel #01 a 12Ω");
+
+        // The dead board stops at ping
+        assert_eq!(report.boards[1].results.len(), 1);
+        assert_eq!(report.boards[1].failures()[0].check, Check::Ping);
+
+        // Board 002 moves to a board with a problem on every check
+        clients.insert("002".to_string(), Arc::new(sim2.board().client()));
+        {
+            let mut state = sim2.state();
+            state.disarmed_voltage = 5.0;
+            state.armed_voltage = 2.0;
+            state.continuities[30] = 20;
+        }
+        sim2.set_continuity(3, 8);
+        let report = check_boards(&script(), &clients, &Thresholds::default(),
+                                  AfterChecks::StayArmed);
+        let failures: Vec<String> = report.boards[1].failures().iter()
+                                                    .map(|r| r.to_string())
+                                                    .collect();
+        assert_eq!(failures, [
+            "Bus voltage 5.00V, should be below 1.00V (default)",
+            "Channel #01 c open, should be connected, reading below 255 (default)",
+            "Unused channel #03 8Ω, should be open, reading 255 or more (default)",
+            "Continuity voltage 2.00V, should be above 3.00V (default)",
+            "Arm voltage 2.00V, should be above 2.50V (default)",
+        ]);
+        assert!(sim2.state().armed);
+
+        // The script can loosen thresholds for a rig
+        let script = Script::from_string("
+board 002 00:00:00:00:01:22
+channel c 002 1
+threshold disarmed 6
+threshold open 8
+threshold continuity 1.5
+fire c
+".to_string()).unwrap();
+        let thresholds = Thresholds::from_script(&script);
+        assert_eq!(thresholds.source(ThresholdName::Armed), ThresholdSource::Default);
+        assert_eq!(thresholds.source(ThresholdName::Open), ThresholdSource::Script { line: SourceLine::script(5) });
+        clients.remove("001");
+        sim2.set_continuity(1, 5);
+        let report = check_boards(&script, &clients, &thresholds, AfterChecks::Disarm);
+        let failures: Vec<String> = report.boards[0].failures().iter()
+                                                    .map(|r| r.to_string())
+                                                    .collect();
+        assert_eq!(failures, ["Arm voltage 2.00V, should be above 2.50V (default)"]);
+        assert!(report.boards[0].results.iter().any(|r| {
+            r.check == Check::UnusedChannel { num: 3 } && r.passed() &&
+            r.source == Some(ThresholdSource::Script { line: SourceLine::script(5) })
+        }));
+    }
+
+    #[test]
+    fn igniter_ranges() {
+        let sim = SimBoard::start(Ipv4Addr::new(127, 0, 1, 23), [0, 0, 0, 0, 1, 0x23]).unwrap();
+        let script = Script::from_string("
+board 001 00:00:00:00:01:23
+igniter ematch 1 5
+channel good    001 1 ematch
+channel shorted 001 2 ematch
+channel series  001 3 ematch
+channel loose   001 4 ematch
+channel plain   001 5
+fire good shorted series loose plain
+".to_string()).unwrap();
+        sim.set_continuity(1, 3);
+        sim.set_continuity(2, 0);
+        sim.set_continuity(3, 9);
+        sim.set_continuity(5, 40);
+        let mut clients = HashMap::new();
+        clients.insert("001".to_string(), Arc::new(sim.board().client()));
+
+        let report = check_boards(&script, &clients, &Thresholds::default(), AfterChecks::Disarm);
+        let failures: Vec<String> = report.boards[0].failures().iter()
+                                                    .map(|r| r.to_string())
+                                                    .collect();
+        assert_eq!(failures, [
+            "Channel #02 shorted 0Ω, should be between 1Ω and 5Ω (igniter 'ematch' defined \
+             on line 3)",
+            "Channel #03 series 9Ω, should be between 1Ω and 5Ω (igniter 'ematch' defined \
+             on line 3)",
+            "Channel #04 loose open, should be between 1Ω and 5Ω (igniter 'ematch' defined \
+             on line 3)",
+        ]);
+    }
+}
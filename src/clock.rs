@@ -0,0 +1,86 @@
+use std::time::{Duration, Instant};
+
+/// Show time measured against a monotonic clock anchored at the start of the
+/// show, so that time spent running cues is made up rather than adding up as
+/// drift. Time spent held, such as at a pause, is not counted.
+#[derive(Debug, Clone)]
+pub struct ShowClock {
+    start: Instant,
+    held: Duration,
+    hold_start: Option<Instant>,
+}
+
+impl ShowClock {
+    /// Start a clock at show time zero.
+    pub fn start() -> ShowClock {
+        ShowClock { start: Instant::now(), held: Duration::from_secs(0), hold_start: None }
+    }
+
+    /// The current show time. This doesn't advance while held.
+    pub fn now(&self) -> Duration {
+        let now = self.hold_start.unwrap_or_else(Instant::now);
+        now.duration_since(self.start) - self.held
+    }
+
+    /// Stop show time advancing, until `resume`.
+    pub fn hold(&mut self) {
+        if self.hold_start.is_none() {
+            self.hold_start = Some(Instant::now());
+        }
+    }
+
+    pub fn resume(&mut self) {
+        if let Some(hold_start) = self.hold_start.take() {
+            self.held += hold_start.elapsed();
+        }
+    }
+
+    pub fn is_held(&self) -> bool {
+        self.hold_start.is_some()
+    }
+
+    /// How long until the show reaches `time`, or zero if it already has.
+    pub fn until(&self, time: Duration) -> Duration {
+        time.checked_sub(self.now()).unwrap_or_default()
+    }
+
+    /// How far the show time is past `time`, negative if it hasn't got there
+    /// yet, in seconds.
+    pub fn lateness(&self, time: Duration) -> f64 {
+        self.now().as_secs_f64() - time.as_secs_f64()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ShowClock;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn anchored_at_start() {
+        let clock = ShowClock::start();
+        let target = Duration::from_millis(50);
+
+        // Work done between waits doesn't push later show times back.
+        thread::sleep(Duration::from_millis(20));
+        assert!(clock.until(target) <= Duration::from_millis(30));
+        thread::sleep(clock.until(target));
+        assert!(clock.now() >= target);
+        assert_eq!(clock.until(target), Duration::from_secs(0));
+        assert!(clock.lateness(target) >= 0.0);
+    }
+
+    #[test]
+    fn hold() {
+        let mut clock = ShowClock::start();
+        clock.hold();
+        assert!(clock.is_held());
+        let held_at = clock.now();
+        thread::sleep(Duration::from_millis(30));
+        assert_eq!(clock.now(), held_at);
+        clock.resume();
+        assert!(!clock.is_held());
+        assert!(clock.now() < Duration::from_millis(30));
+    }
+}
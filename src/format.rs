@@ -0,0 +1,489 @@
+use std::cmp::Ordering;
+
+use script::{Cue, Script};
+
+/// Pad `word` with spaces to `width` characters.
+fn pad(word: &str, width: usize) -> String {
+    format!("{:width$}", word, width = width)
+}
+
+/// Order board IDs numerically where both are numbers, so that board `9`
+/// comes before board `10`, and otherwise as strings after any numbers.
+fn cmp_ids(a: &str, b: &str) -> Ordering {
+    match (a.parse::<u64>(), b.parse::<u64>()) {
+        (Ok(x), Ok(y)) => x.cmp(&y).then_with(|| a.cmp(b)),
+        (Ok(_), Err(_)) => Ordering::Less,
+        (Err(_), Ok(_)) => Ordering::Greater,
+        (Err(_), Err(_)) => a.cmp(b),
+    }
+}
+
+/// Whether a cue defines something, and so is gathered into the blocks at
+/// the top of a formatted script.
+fn is_definition(cue: &Cue) -> bool {
+    matches!(*cue, Cue::Board { .. } | Cue::Channel { .. } | Cue::Group { .. } |
+                   Cue::Threshold { .. } | Cue::Igniter { .. })
+}
+
+/// Lines which keep their order, with an `at` cue joined onto the line of
+/// the cue after it. An empty line is a blank line.
+#[derive(Default)]
+struct Lines {
+    lines: Vec<String>,
+    at: Option<String>,
+}
+
+impl Lines {
+    fn push(&mut self, line: String) {
+        self.lines.extend(self.at.take());
+        self.lines.push(line);
+    }
+
+    fn push_cue(&mut self, cue: &Cue) {
+        let line = cue.to_string();
+        match (self.at.take(), cue) {
+            (Some(prefix), &Cue::At { .. }) => {
+                self.lines.push(prefix);
+                self.at = Some(line);
+            },
+            (Some(prefix), _) => self.lines.push(format!("{} {}", prefix, line)),
+            (None, &Cue::At { .. }) => self.at = Some(line),
+            (None, _) => self.lines.push(line),
+        }
+    }
+
+    /// The lines, with no blank lines at either end or two in a row.
+    fn finish(mut self) -> Vec<String> {
+        self.lines.extend(self.at.take());
+        let mut lines: Vec<String> = Vec::new();
+        for line in self.lines {
+            if !line.is_empty() || lines.last().is_some_and(|l| !l.is_empty()) {
+                lines.push(line);
+            }
+        }
+        if lines.last().is_some_and(|l| l.is_empty()) {
+            lines.pop();
+        }
+        lines
+    }
+}
+
+/// A definition and the comments directly above it.
+type Definition = (Vec<String>, Cue);
+
+/// The board a board or channel definition is for.
+fn board_of(cue: &Cue) -> &str {
+    match *cue {
+        Cue::Board { ref board_id, .. } | Cue::Channel { ref board_id, .. } => board_id,
+        _ => "",
+    }
+}
+
+/// Render definitions of one kind as lines, each after its comments, with
+/// `line` giving the text of each definition from its cue.
+fn definition_lines<F>(definitions: &[&Definition], line: F) -> Vec<String>
+    where F: Fn(&Cue) -> String
+{
+    definitions.iter().flat_map(|d| d.0.iter().cloned().chain(Some(line(&d.1)))).collect()
+}
+
+/// Lay out the blocks of a formatted script: `header`, then the definitions,
+/// each after the comments that went with it, then `body`.
+fn render(header: Vec<String>, definitions: &[Definition], body: Vec<String>) -> String {
+    let mut boards: Vec<&Definition> = Vec::new();
+    let mut channels: Vec<&Definition> = Vec::new();
+    let mut groups: Vec<&Definition> = Vec::new();
+    let mut settings: Vec<&Definition> = Vec::new();
+    for definition in definitions {
+        match definition.1 {
+            Cue::Board { .. } => boards.push(definition),
+            Cue::Channel { .. } => channels.push(definition),
+            Cue::Group { .. } => groups.push(definition),
+            _ => settings.push(definition),
+        }
+    }
+    boards.sort_by(|a, b| cmp_ids(board_of(&a.1), board_of(&b.1)));
+    channels.sort_by(|a, b| match (&a.1, &b.1) {
+        (&Cue::Channel { board_id: ref x, num: m, .. },
+         &Cue::Channel { board_id: ref y, num: n, .. }) => cmp_ids(x, y).then(m.cmp(&n)),
+        _ => Ordering::Equal,
+    });
+
+    let mut blocks: Vec<Vec<String>> = vec![header, definition_lines(&settings, Cue::to_string)];
+
+    let id_width = boards.iter().map(|b| board_of(&b.1).len()).max().unwrap_or(0);
+    blocks.push(definition_lines(&boards, |cue| match *cue {
+        Cue::Board { ref board_id, mac_address } =>
+            Cue::Board { board_id: pad(board_id, id_width), mac_address }.to_string(),
+        _ => cue.to_string(),
+    }));
+
+    // Channel lines are aligned without their igniter types, which are then
+    // added in a column of their own.
+    let widths = |cue: &Cue| match *cue {
+        Cue::Channel { ref name, ref board_id, .. } => (name.len(), board_id.len()),
+        _ => (0, 0),
+    };
+    let name_width = channels.iter().map(|c| widths(&c.1).0).max().unwrap_or(0);
+    let board_width = channels.iter().map(|c| widths(&c.1).1).max().unwrap_or(0);
+    let channel_line = |cue: &Cue| match *cue {
+        Cue::Channel { ref name, ref board_id, num, .. } => Cue::Channel {
+            name: pad(name, name_width), board_id: pad(board_id, board_width), num, igniter: None
+        }.to_string(),
+        _ => cue.to_string(),
+    };
+    let line_width = channels.iter().map(|c| channel_line(&c.1).len()).max().unwrap_or(0);
+    blocks.push(definition_lines(&channels, |cue| match *cue {
+        Cue::Channel { igniter: Some(ref igniter), .. } =>
+            format!("{} {}", pad(&channel_line(cue), line_width), igniter),
+        _ => channel_line(cue),
+    }));
+
+    let name_width = groups.iter().map(|g| match g.1 {
+        Cue::Group { ref name, .. } => name.len(),
+        _ => 0,
+    }).max().unwrap_or(0);
+    blocks.push(definition_lines(&groups, |cue| match *cue {
+        Cue::Group { ref name, ref channels } =>
+            Cue::Group { name: pad(name, name_width), channels: channels.clone() }.to_string(),
+        _ => cue.to_string(),
+    }));
+
+    blocks.push(body);
+
+    // Separate the blocks with blank lines, leaving out any that are empty.
+    let mut text = String::new();
+    for block in blocks.iter().filter(|b| !b.is_empty()) {
+        if !text.is_empty() {
+            text.push('\n');
+        }
+        for line in block {
+            text.push_str(line);
+            text.push('\n');
+        }
+    }
+    text
+}
+
+/// Render cues as canonical script text.
+///
+/// Thresholds and igniter types, then board, channel and group definitions
+/// are gathered into blocks at the top, with their columns aligned.
+/// Thresholds and igniters keep their order. Boards are sorted by ID, with
+/// numeric IDs in numeric order, and channels by board and channel number,
+/// while groups keep their order as a group may contain earlier groups. The
+/// remaining cues follow in order, one per line, with an `at` cue joined onto
+/// the line of the cue after it.
+pub fn format_cues(cues: &[Cue]) -> String {
+    let mut definitions = Vec::new();
+    let mut body = Lines::default();
+    for cue in cues {
+        if is_definition(cue) {
+            definitions.push((Vec::new(), cue.clone()));
+        } else {
+            body.push_cue(cue);
+        }
+    }
+    render(Vec::new(), &definitions, body.finish())
+}
+
+/// Render a script as canonical script text. Included scripts and patch files
+/// appear inline, and comments and channel descriptions are not kept.
+pub fn format_script(script: &Script) -> String {
+    format_cues(&script.cues)
+}
+
+/// Render the text of a script file in canonical form, as `format_cues`, but
+/// keeping its comments and its `include`, `patch` and `labels` commands,
+/// which are not followed. Comments directly above a definition move with it.
+/// Other comments, blank lines and commands keep their place among the cues
+/// which aren't definitions, or above the definitions if they come before
+/// any such cue, as do comments at the very top of the file. Lines which
+/// don't parse are kept as they are.
+pub fn format_source(source: &str) -> String {
+    let mut header = Lines::default();
+    let mut body = Lines::default();
+    let mut started = false;
+    let mut definitions = Vec::new();
+    let mut comments: Vec<String> = Vec::new();
+
+    for (idx, line) in source.lines().enumerate() {
+        let trimmed = line.trim();
+        let command = trimmed.split_whitespace().next().unwrap_or("");
+        if command == "#" {
+            comments.push(trimmed.to_string());
+            continue;
+        }
+
+        let cues = match command {
+            "" | "include" | "patch" | "labels" => None,
+            _ => Cue::from_line(trimmed, idx + 1).ok(),
+        };
+        if let Some(ref cues) = cues {
+            if cues.len() == 1 && is_definition(&cues[0]) {
+                definitions.push((comments.split_off(0), cues[0].clone()));
+                continue;
+            }
+        }
+
+        // Comments at the very top of the file stay there.
+        let is_cue = !matches!(command, "" | "include" | "patch" | "labels");
+        if idx == comments.len() {
+            for comment in comments.drain(..) {
+                header.push(comment);
+            }
+        }
+        started = started || is_cue;
+        let lines = if started { &mut body } else { &mut header };
+        for comment in comments.drain(..) {
+            lines.push(comment);
+        }
+        match cues {
+            Some(cues) => for cue in &cues {
+                lines.push_cue(cue);
+            },
+            None if command.is_empty() => lines.push(String::new()),
+            None if is_cue => lines.push(trimmed.to_string()),
+            None => lines.push(format!("{} {}", command, trimmed[command.len()..].trim())),
+        }
+    }
+    let lines = if started { &mut body } else { &mut header };
+    for comment in comments {
+        lines.push(comment);
+    }
+
+    render(header.finish(), &definitions, body.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_cues, format_script, format_source};
+    use script::{Cue, Script};
+    use std::time::Duration;
+
+    #[test]
+    fn cues() {
+        let cues = [
+            Cue::Sleep { time: Duration::from_millis(250) },
+            Cue::FireGap { time: Duration::from_secs(2) },
+            Cue::At { time: Duration::from_nanos(61_500_000_001) },
+            Cue::Say { message: "".to_string() },
+            Cue::Print { message: "Hello  there".to_string() },
+            Cue::Pause,
+        ];
+        let lines: Vec<String> = cues.iter().map(|c| c.to_string()).collect();
+        assert_eq!(lines, vec!["sleep 0.25", "firegap 2", "at 01:01.500000001", "say",
+                               "print Hello  there", "pause"]);
+    }
+
+    #[test]
+    fn canonical() {
+        let script = Script::from_string("
+# Blocks are sorted and aligned
+board 2 00:00:00:00:00:02
+threshold  armed 20.0
+igniter ematch 1   5
+board 001 D8:80:39:E0:7D:37
+channel    b   2 3 ematch
+channel a_long 001 1
+channel c 2    1
+group both   a_long  b
+sleep   1.500s
+at 0:05
+fire both
+group big both c
+at 10
+at 20 fire c
+at 30
+".to_string()).unwrap();
+
+        assert_eq!(format_script(&script), "\
+threshold armed 20
+igniter ematch 1 5
+
+board 001 d8:80:39:e0:7d:37
+board 2   00:00:00:00:00:02
+
+channel a_long 001 1
+channel c      2   1
+channel b      2   3 ematch
+
+group both a_long b
+group big  both c
+
+sleep 1.5
+at 00:05.000 fire both
+at 00:10.000
+at 00:20.000 fire c
+at 00:30.000
+");
+    }
+
+    #[test]
+    fn idempotent() {
+        let script = Script::from_string("
+board 9 00:00:00:00:00:09
+channel x 9 30
+pause
+fire x
+board 10 00:00:00:00:00:10
+".to_string()).unwrap();
+        let text = format_script(&script);
+        let reparsed = Script::from_string(text.clone()).unwrap();
+        assert_eq!(format_script(&reparsed), text);
+        assert_eq!(reparsed.boards, script.boards);
+        assert_eq!(reparsed.duration, script.duration);
+    }
+
+    #[test]
+    fn numeric_ids() {
+        let script = Script::from_string("
+board 10 00:00:00:00:00:10
+board b 00:00:00:00:00:0b
+board 9 00:00:00:00:00:09
+channel x 10 1
+channel y 9 2
+".to_string()).unwrap();
+        assert_eq!(format_script(&script), "\
+board 9  00:00:00:00:00:09
+board 10 00:00:00:00:00:10
+board b  00:00:00:00:00:0b
+
+channel y 9  2
+channel x 10 1
+");
+    }
+
+    #[test]
+    fn source() {
+        let text = "\
+# Rehearsal script
+
+include  rig.fire
+# The spare board
+board 3 00:00:00:00:00:03
+  patch extra.csv
+channel c 3 1
+# Intro
+  say Hello there
+  print Welcome
+
+at 5
+# Bang
+fire c
+labels   music.txt
+# The end
+";
+        assert_eq!(format_source(text), "\
+# Rehearsal script
+
+include rig.fire
+patch extra.csv
+
+# The spare board
+board 3 00:00:00:00:00:03
+
+channel c 3 1
+
+# Intro
+say Hello there
+print Welcome
+
+at 00:05.000
+# Bang
+fire c
+labels music.txt
+# The end
+");
+        assert_eq!(format_source(&format_source(text)), format_source(text));
+    }
+
+    /// A small xorshift generator, so that the round trip test is repeatable.
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: u64) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 % n
+        }
+    }
+
+    /// Generate a valid script whose cues are already in canonical order.
+    fn random_cues(rng: &mut Rng) -> Vec<Cue> {
+        let words = ["Ready", "Go", "Finale", "#1", "ten  nine"];
+        let mut cues = Vec::new();
+        let mut channels: Vec<String> = Vec::new();
+
+        let nboards = 1 + rng.below(3);
+        for board in 0..nboards {
+            cues.push(Cue::Board {
+                board_id: format!("b{}", board),
+                mac_address: [0xd8, 0x80, 0x39, 0, 0, board as u8],
+            });
+        }
+        for board in 0..nboards {
+            for num in 1..31 {
+                if rng.below(4) == 0 {
+                    let name = format!("ch{}_{}", board, num);
+                    cues.push(Cue::Channel {
+                        name: name.clone(), board_id: format!("b{}", board), num, igniter: None
+                    });
+                    channels.push(name);
+                }
+            }
+        }
+
+        // Show time is overestimated by allowing ten seconds for sequencing
+        // each fire cue, so that at cues never go backwards.
+        let mut show_time = Duration::from_secs(0);
+        let mut slept = true;
+        while !channels.is_empty() {
+            match rng.below(7) {
+                0 => cues.push(Cue::Say { message: words[rng.below(5) as usize].to_string() }),
+                1 => cues.push(Cue::Print { message: words[rng.below(5) as usize].to_string() }),
+                2 => cues.push(Cue::FireGap { time: Duration::from_millis(rng.below(1000)) }),
+                3 => {
+                    show_time += Duration::from_millis(1 + rng.below(100_000));
+                    cues.push(Cue::At { time: show_time });
+                    slept = true;
+                },
+                4 => {
+                    cues.push(Cue::Pause);
+                    slept = true;
+                },
+                5 => {
+                    let time = Duration::from_micros(1 + rng.below(10_000_000));
+                    show_time += time;
+                    cues.push(Cue::Sleep { time });
+                    slept = true;
+                },
+                _ => if slept {
+                    let n = 1 + rng.below(channels.len().min(8) as u64);
+                    let fired: Vec<String> = (0..n).map(|_| {
+                        channels.remove(rng.below(channels.len() as u64) as usize)
+                    }).collect();
+                    cues.push(Cue::Fire { channels: fired });
+                    show_time += Duration::from_secs(10);
+                    slept = false;
+                },
+            }
+        }
+        cues
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut rng = Rng(0x1234_5678_9abc_def0);
+        for _ in 0..200 {
+            let cues = random_cues(&mut rng);
+            let text = format_cues(&cues);
+            let script = Script::from_string(text.clone()).unwrap_or_else(|e| {
+                panic!("{}\nin script:\n{}", e, text)
+            });
+            assert_eq!(script.cues, cues, "in script:\n{}", text);
+            assert_eq!(format_script(&script), text);
+        }
+    }
+}
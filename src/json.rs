@@ -0,0 +1,250 @@
+use serde::{Deserialize, Serialize};
+use serde_json;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use patch::ChannelInfo;
+use script::{Cue, Script, ScriptError, SourceLine};
+
+/// A show as written to JSON: its cues in order, each with the line it was
+/// read from, and the file for lines not in the script itself.
+#[derive(Serialize)]
+struct ShowOut<'a> {
+    cues: Vec<LineOut<'a>>,
+}
+
+#[derive(Serialize)]
+struct LineOut<'a> {
+    line: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file: Option<&'a Path>,
+    #[serde(flatten)]
+    cue: &'a Cue,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    info: Option<&'a ChannelInfo>,
+}
+
+/// A show as read from JSON, where line numbers are optional.
+#[derive(Deserialize)]
+struct ShowIn {
+    cues: Vec<LineIn>,
+}
+
+#[derive(Deserialize)]
+struct LineIn {
+    line: Option<usize>,
+    file: Option<PathBuf>,
+    #[serde(flatten)]
+    cue: Cue,
+    info: Option<ChannelInfo>,
+}
+
+/// Write a script's cues as JSON, for example:
+///
+/// ```text
+/// {"cues": [{"line": 1, "cue": "board", "board_id": "001", "mac_address": "d8:80:39:e0:7d:37"},
+///           {"line": 4, "cue": "sleep", "time": "1.5"},
+///           {"line": 5, "cue": "at", "time": "01:00.000"},
+///           {"line": 5, "cue": "fire", "channels": ["ch1", "ch2"]}]}
+/// ```
+///
+/// Cues are tagged with their script command, and MAC addresses and times are
+/// written as they would be in a script. Cues read from an included file,
+/// patch file or label track also have the `file` their line is in. Channels
+/// described by a patch file also have an `info` object with the `position`,
+/// `effect` and `notes`.
+pub fn to_json(script: &Script) -> String {
+    let show = ShowOut {
+        cues: script.cues.iter()
+                         .zip(&script.lines)
+                         .map(|(cue, line)| {
+                             let info = match *cue {
+                                 Cue::Channel { ref name, .. } => script.channel_info.get(name),
+                                 _ => None,
+                             };
+                             LineOut { line: line.lineno, file: line.path.as_deref(), cue, info }
+                         })
+                         .collect(),
+    };
+    serde_json::to_string_pretty(&show).unwrap()
+}
+
+/// Read a show from JSON in the format written by `to_json`, checking it just
+/// as if it had been read from a script file. Each cue must also be one that
+/// could be written in a script, so names must be single words and messages
+/// single lines. Errors are reported against each cue's `line` and `file`, or
+/// its position in the list of cues if it has no line.
+pub fn from_json(json: &str) -> Result<Script, Vec<ScriptError>> {
+    let show: ShowIn = serde_json::from_str(json).map_err(|e| vec![ScriptError::Json(e)])?;
+
+    let mut errors = Vec::new();
+    let mut cues = Vec::new();
+    let mut channel_info = HashMap::new();
+    for (idx, line) in show.cues.into_iter().enumerate() {
+        let lineno = line.line.unwrap_or(idx + 1);
+        let error = match (&line.cue, &line.info) {
+            (&Cue::Fire { ref channels }, _) | (&Cue::Group { ref channels, .. }, _)
+                if channels.is_empty() => Some("Wrong number of arguments"),
+            (cue, _) if !cue.is_writable() => Some("Not valid in a script"),
+            (&Cue::Channel { .. }, _) | (_, &None) => None,
+            (_, &Some(_)) => Some("Only channels have info"),
+        };
+        if let Some(error) = error {
+            let error = ScriptError::Parse { lineno, error };
+            errors.push(match line.file {
+                Some(path) => ScriptError::Included { path, error: Box::new(error) },
+                None => error,
+            });
+            continue;
+        }
+
+        if let (Cue::Channel { name, .. }, Some(info)) = (&line.cue, line.info) {
+            channel_info.insert(name.clone(), info);
+        }
+        cues.push((SourceLine { path: line.file, lineno }, line.cue));
+    }
+
+    match Script::from_cues_all_errors(cues) {
+        Ok(mut script) if errors.is_empty() => {
+            script.channel_info = channel_info;
+            Ok(script)
+        },
+        Ok(_) => Err(errors),
+        Err(more) => {
+            errors.extend(more);
+            // Errors in the script itself come first, then each other file's.
+            errors.sort_by_key(|e| match *e {
+                ScriptError::Included { ref path, ref error } =>
+                    (Some(path.clone()), error.lineno()),
+                _ => (None, e.lineno()),
+            });
+            Err(errors)
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_json, to_json};
+    use patch::ChannelInfo;
+    use script::{Cue, Script, ScriptError, SourceLine};
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    #[test]
+    fn round_trip() {
+        let script = Script::from_string("
+board 001 d8:80:39:e0:7d:37
+channel ch1 001 1
+channel ch2 001 2
+group both ch1 ch2
+firegap 250ms
+say Ready
+sleep 1.5
+at 01:00 fire both
+pause
+".to_string()).unwrap();
+
+        let json = to_json(&script);
+        assert!(json.contains("\"mac_address\": \"d8:80:39:e0:7d:37\""));
+        assert!(json.contains("\"time\": \"01:00.000\""));
+        assert_eq!(from_json(&json).unwrap(), script);
+    }
+
+    #[test]
+    fn authored() {
+        let json = r#"{"cues": [
+            {"cue": "board", "board_id": "001", "mac_address": "00:00:00:00:00:01"},
+            {"cue": "channel", "name": "ch1", "board_id": "001", "num": 1},
+            {"cue": "sleep", "time": "2s"},
+            {"cue": "fire", "channels": ["ch1"]}
+        ]}"#;
+        let script = from_json(json).unwrap();
+        let lines: Vec<SourceLine> = (1..5).map(SourceLine::script).collect();
+        assert_eq!(script.lines, lines);
+        assert_eq!(script.duration, Duration::from_secs(2));
+        assert_eq!(script.cues[3], Cue::Fire { channels: vec!["ch1".to_string()] });
+    }
+
+    #[test]
+    fn checked() {
+        let json = r#"{"cues": [
+            {"line": 3, "cue": "board", "board_id": "001", "mac_address": "00:00:00:00:00:01"},
+            {"line": 4, "cue": "channel", "name": "ch1", "board_id": "002", "num": 1},
+            {"line": 8, "cue": "fire", "channels": []},
+            {"line": 9, "cue": "fire", "channels": ["ch2"]}
+        ]}"#;
+        let errors: Vec<String> = from_json(json).unwrap_err().iter().map(|e| e.to_string()).collect();
+        assert_eq!(errors, vec!["line 4: board '002' is not defined",
+                                "line 8: Wrong number of arguments",
+                                "line 9: channel 'ch2' is not defined"]);
+    }
+
+    #[test]
+    fn unwritable() {
+        let json = r#"{"cues": [
+            {"cue": "board", "board_id": "my board", "mac_address": "00:00:00:00:00:01"},
+            {"cue": "channel", "name": "big cake", "board_id": "001", "num": 1},
+            {"cue": "say", "message": "hello\nfire x"},
+            {"cue": "sleep", "time": "1", "info": {"notes": "Not a channel"}}
+        ]}"#;
+        let errors: Vec<String> = from_json(json).unwrap_err().iter().map(|e| e.to_string()).collect();
+        assert_eq!(errors, vec!["line 1: Not valid in a script",
+                                "line 2: Not valid in a script",
+                                "line 3: Not valid in a script",
+                                "line 4: Only channels have info"]);
+    }
+
+    #[test]
+    fn channel_info() {
+        let json = r#"{"cues": [
+            {"cue": "board", "board_id": "001", "mac_address": "00:00:00:00:00:01"},
+            {"cue": "channel", "name": "ch1", "board_id": "001", "num": 1,
+             "info": {"position": "Left", "effect": "Cake"}},
+            {"cue": "channel", "name": "ch2", "board_id": "001", "num": 2}
+        ]}"#;
+        let script = from_json(json).unwrap();
+        assert_eq!(script.channel_info["ch1"], ChannelInfo {
+            position: "Left".to_string(), effect: "Cake".to_string(), notes: "".to_string()
+        });
+        assert!(!script.channel_info.contains_key("ch2"));
+        assert_eq!(from_json(&to_json(&script)).unwrap(), script);
+    }
+
+    #[test]
+    fn invalid() {
+        let json = r#"{"cues": [{"cue": "sleep", "time": "soon"}]}"#;
+        match from_json(json).unwrap_err()[0] {
+            ScriptError::Json(ref e) => assert!(e.to_string().contains("invalid time")),
+            ref e => panic!("expected Json, got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn files() {
+        let json = r#"{"cues": [
+            {"line": 1, "file": "patch.fire", "cue": "board", "board_id": "001",
+             "mac_address": "00:00:00:00:00:01"},
+            {"line": 2, "file": "patch.fire", "cue": "channel", "name": "ch1", "board_id": "001",
+             "num": 1},
+            {"line": 2, "cue": "sleep", "time": "1"},
+            {"line": 3, "cue": "fire", "channels": ["ch1"]}
+        ]}"#;
+        let script = from_json(json).unwrap();
+        let patch = Some(PathBuf::from("patch.fire"));
+        assert_eq!(script.lines[1], SourceLine { path: patch, lineno: 2 });
+        assert_eq!(script.lines[2], SourceLine::script(2));
+        assert_eq!(from_json(&to_json(&script)).unwrap(), script);
+
+        let json = r#"{"cues": [
+            {"line": 1, "file": "patch.fire", "cue": "board", "board_id": "001",
+             "mac_address": "00:00:00:00:00:01"},
+            {"line": 2, "file": "patch.fire", "cue": "channel", "name": "ch1", "board_id": "002",
+             "num": 1}
+        ]}"#;
+        let errors = from_json(json).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].to_string(), "patch.fire: line 2: board '002' is not defined");
+    }
+}
@@ -1,5 +1,19 @@
+extern crate serde;
+extern crate serde_json;
+extern crate signal_hook;
+
+pub mod audacity;
+pub mod checks;
+pub mod clock;
+pub mod format;
+pub mod json;
+pub mod lint;
+pub mod patch;
 pub mod portfire;
 pub mod script;
+pub mod show;
+pub mod simulator;
+pub mod timeline;
 
 #[cfg(feature="tts")]
 pub mod tts;
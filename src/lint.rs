@@ -0,0 +1,208 @@
+use std::fmt;
+use std::time::Duration;
+
+use script::{Cue, Script, SourceLine};
+
+/// Rough speaking rate used to estimate how long a `say` cue takes.
+const SPEECH_WORDS_PER_MINUTE: u64 = 150;
+
+/// Something legal but probably wrong in a script.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptWarning {
+    ChannelNeverFired { line: SourceLine, channel: String },
+    BoardWithoutChannels { line: SourceLine, board_id: String },
+    SayLongerThanSleep { line: SourceLine, speech: Duration, sleep: Duration },
+    PauseAfterPause { line: SourceLine },
+    NeverFires,
+}
+
+impl ScriptWarning {
+    /// The line the warning is about, if any.
+    pub fn line(&self) -> Option<&SourceLine> {
+        match *self {
+            ScriptWarning::ChannelNeverFired { ref line, .. } |
+            ScriptWarning::BoardWithoutChannels { ref line, .. } |
+            ScriptWarning::SayLongerThanSleep { ref line, .. } |
+            ScriptWarning::PauseAfterPause { ref line } => Some(line),
+            ScriptWarning::NeverFires => None,
+        }
+    }
+}
+
+impl fmt::Display for ScriptWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ScriptWarning::ChannelNeverFired { ref line, ref channel } =>
+                write!(f, "{}: channel '{}' is never fired", line, channel),
+            ScriptWarning::BoardWithoutChannels { ref line, ref board_id } =>
+                write!(f, "{}: board '{}' has no channels", line, board_id),
+            ScriptWarning::SayLongerThanSleep { ref line, speech, sleep } =>
+                write!(f, "{}: speech takes about {:.1}s but the following sleep is \
+                           only {:.1}s", line, speech.as_secs_f64(), sleep.as_secs_f64()),
+            ScriptWarning::PauseAfterPause { ref line } =>
+                write!(f, "{}: pause immediately after another pause", line),
+            ScriptWarning::NeverFires => write!(f, "script never fires anything"),
+        }
+    }
+}
+
+/// Estimate how long it takes to say `message`.
+fn speech_time(message: &str) -> Duration {
+    let words = message.split_whitespace().count() as u64;
+    Duration::from_millis(words * 60_000 / SPEECH_WORDS_PER_MINUTE)
+}
+
+/// Check a parsed script for things which are allowed but probably mistakes.
+pub fn lint(script: &Script) -> Vec<ScriptWarning> {
+    let mut warnings = Vec::new();
+
+    let mut fired: Vec<String> = Vec::new();
+    for cue in &script.cues {
+        if let Cue::Fire { ref channels } = *cue {
+            fired.extend(script.expand_channels(channels));
+        }
+    }
+
+    // Definitions are checked in script order.
+    for (cue, line) in script.cues.iter().zip(&script.lines) {
+        match *cue {
+            Cue::Board { ref board_id, .. }
+                if !script.channels.values().any(|(b, _)| b == board_id) =>
+                warnings.push(ScriptWarning::BoardWithoutChannels {
+                    line: line.clone(), board_id: board_id.clone()
+                }),
+            Cue::Channel { ref name, .. } if !fired.contains(name) =>
+                warnings.push(ScriptWarning::ChannelNeverFired {
+                    line: line.clone(), channel: name.clone()
+                }),
+            _ => {},
+        }
+    }
+
+    // Only cues which run during the show count for what follows what.
+    let running: Vec<(&Cue, &SourceLine)> = script.cues.iter()
+        .zip(&script.lines)
+        .filter(|&(cue, _)| !matches!(*cue, Cue::Board { .. } | Cue::Channel { .. } |
+                                            Cue::Group { .. } | Cue::Threshold { .. } |
+                                            Cue::Igniter { .. }))
+        .collect();
+
+    let mut show_time = Duration::from_secs(0);
+    for (idx, &(cue, line)) in running.iter().enumerate() {
+        match *cue {
+            Cue::Say { ref message } => {
+                // Find the next cue which waits, skipping any which don't.
+                let speech = speech_time(message);
+                let sleep = running[idx + 1..].iter().filter_map(|&(next, _)| match *next {
+                    Cue::Sleep { time } => Some(Some(time)),
+                    Cue::At { time } => Some(Some(time.saturating_sub(show_time))),
+                    Cue::Print { .. } | Cue::FireGap { .. } => None,
+                    _ => Some(None),
+                }).next();
+                if let Some(Some(sleep)) = sleep {
+                    if speech > sleep {
+                        warnings.push(ScriptWarning::SayLongerThanSleep {
+                            line: line.clone(), speech, sleep
+                        });
+                    }
+                }
+            },
+            Cue::Pause if idx > 0 && *running[idx - 1].0 == Cue::Pause =>
+                warnings.push(ScriptWarning::PauseAfterPause { line: line.clone() }),
+            Cue::Sleep { time } => show_time += time,
+            Cue::At { time } => show_time = time,
+            _ => {},
+        }
+    }
+
+    if fired.is_empty() {
+        warnings.push(ScriptWarning::NeverFires);
+    }
+
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lint, ScriptWarning};
+    use script::{Script, SourceLine};
+    use std::time::Duration;
+    use std::{env, fs};
+
+    #[test]
+    fn clean() {
+        let script = Script::from_string("
+board 001 00:00:00:00:00:01
+channel ch1 001 1
+say Three two one
+sleep 2
+fire ch1
+pause
+".to_string()).unwrap();
+        assert_eq!(lint(&script), vec![]);
+    }
+
+    #[test]
+    fn warnings() {
+        let script = Script::from_string("
+board 001 00:00:00:00:00:01
+board 002 00:00:00:00:00:02
+channel ch1 001 1
+channel ch2 001 2
+say Ready
+print Ready
+sleep 0.1
+fire ch1
+pause
+pause
+say Ten nine eight seven six five four
+at 00:01
+".to_string()).unwrap();
+
+        let warnings = lint(&script);
+        assert_eq!(warnings, vec![
+            ScriptWarning::BoardWithoutChannels {
+                line: SourceLine::script(3), board_id: "002".to_string()
+            },
+            ScriptWarning::ChannelNeverFired {
+                line: SourceLine::script(5), channel: "ch2".to_string()
+            },
+            ScriptWarning::SayLongerThanSleep {
+                line: SourceLine::script(6), speech: Duration::from_millis(400),
+                sleep: Duration::from_millis(100)
+            },
+            ScriptWarning::PauseAfterPause { line: SourceLine::script(11) },
+            ScriptWarning::SayLongerThanSleep {
+                line: SourceLine::script(12), speech: Duration::from_millis(2800),
+                sleep: Duration::from_millis(900)
+            },
+        ]);
+        assert_eq!(warnings[2].to_string(),
+                   "line 6: speech takes about 0.4s but the following sleep is only 0.1s");
+    }
+
+    #[test]
+    fn never_fires() {
+        let script = Script::from_string("print Nothing to see\n".to_string()).unwrap();
+        assert_eq!(lint(&script), vec![ScriptWarning::NeverFires]);
+    }
+
+    #[test]
+    fn included() {
+        let dir = env::temp_dir().join("iris-test-lint-included");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("patch.fire"), "board 001 00:00:00:00:00:01\nchannel ch1 001 1\n\
+                                           channel ch2 001 2\n").unwrap();
+        fs::write(dir.join("show.fire"), "include patch.fire\nsleep 1\nfire ch1\n").unwrap();
+
+        let script = Script::from_file(dir.join("show.fire")).unwrap();
+        let patch = dir.join("patch.fire");
+        let warnings = lint(&script);
+        assert_eq!(warnings, vec![ScriptWarning::ChannelNeverFired {
+            line: SourceLine { path: Some(patch.clone()), lineno: 3 }, channel: "ch2".to_string()
+        }]);
+        assert_eq!(warnings[0].to_string(), format!("line 3 of {}: channel 'ch2' is never fired",
+                                                    patch.display()));
+    }
+}
@@ -0,0 +1,237 @@
+use std::io::prelude::*;
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use script::{ScriptError, parse_mac_address};
+
+/// What a channel is and where it is in the rig, as described by a patch file.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChannelInfo {
+    pub position: String,
+    pub effect: String,
+    pub notes: String,
+}
+
+/// A channel row of a patch file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatchChannel {
+    pub name: String,
+    pub num: u8,
+    /// The type of igniter on the channel, if given.
+    pub igniter: Option<String>,
+    pub info: ChannelInfo,
+}
+
+/// One row of a patch file, describing a board and usually one of its
+/// channels. The MAC address need only be given on one row for each board.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatchRow {
+    pub lineno: usize,
+    pub board_id: String,
+    pub mac_address: Option<[u8; 6]>,
+    pub channel: Option<PatchChannel>,
+}
+
+/// Split one CSV line into fields. Fields may be quoted with double quotes,
+/// in which case they may contain commas and doubled quotes.
+fn split_csv(line: &str) -> Option<Vec<String>> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut chars = line.chars().peekable();
+    let mut quoted = false;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted => {
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    field.push('"');
+                } else {
+                    quoted = false;
+                }
+            },
+            '"' if field.trim().is_empty() => {
+                field.clear();
+                quoted = true;
+            },
+            ',' if !quoted => fields.push(field.split_off(0)),
+            _ => field.push(c),
+        }
+    }
+
+    if quoted {
+        return None;
+    }
+    fields.push(field);
+    Some(fields.iter().map(|f| f.trim().to_string()).collect())
+}
+
+/// Read the rows of a CSV patch file.
+///
+/// The first line that isn't blank or a `#` comment is a header naming the
+/// columns, in any order: `board`, `mac`, `channel` and `number` are
+/// required, and `igniter`, `position`, `effect` and `notes` are optional.
+/// An igniter must be a type defined in the script. Any other columns are
+/// ignored. Rows with an empty `channel` only describe a board.
+pub fn read_patch<B: BufRead>(bf: B) -> Result<Vec<PatchRow>, Vec<ScriptError>> {
+    let mut columns: Option<HashMap<String, usize>> = None;
+    let mut rows: Vec<PatchRow> = Vec::new();
+    let mut errors: Vec<ScriptError> = Vec::new();
+
+    for (lineno, line) in bf.lines().enumerate() {
+        let lineno = lineno + 1;
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                errors.push(ScriptError::from(e));
+                break;
+            },
+        };
+
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+
+        let fields = match split_csv(&line) {
+            Some(fields) => fields,
+            None => {
+                errors.push(ScriptError::Parse { lineno, error: "Unterminated quote" });
+                continue;
+            },
+        };
+
+        // The first row names the columns.
+        let cols = match columns {
+            Some(ref cols) => cols,
+            None => {
+                let cols: HashMap<String, usize> = fields.iter()
+                                                         .enumerate()
+                                                         .map(|(i, f)| (f.to_lowercase(), i))
+                                                         .collect();
+                if ["board", "mac", "channel", "number"].iter().any(|c| !cols.contains_key(*c)) {
+                    errors.push(ScriptError::Parse {
+                        lineno, error: "Patch header must have board, mac, channel and number columns"
+                    });
+                    return Err(errors);
+                }
+                columns = Some(cols);
+                continue;
+            },
+        };
+        let field = |name: &str| -> String {
+            cols.get(name).and_then(|&i| fields.get(i)).cloned().unwrap_or_default()
+        };
+
+        let board_id = field("board");
+        if board_id.is_empty() {
+            errors.push(ScriptError::Parse { lineno, error: "Missing board" });
+            continue;
+        }
+
+        let mac_address = match field("mac").as_str() {
+            "" => None,
+            mac => match parse_mac_address(mac) {
+                Some(mac) => Some(mac),
+                None => {
+                    errors.push(ScriptError::Parse { lineno, error: "Invalid MAC address" });
+                    continue;
+                },
+            },
+        };
+
+        let channel = match field("channel").as_str() {
+            "" => None,
+            name => match field("number").parse() {
+                Ok(num) => Some(PatchChannel {
+                    name: name.to_string(),
+                    num,
+                    igniter: Some(field("igniter")).filter(|i| !i.is_empty()),
+                    info: ChannelInfo {
+                        position: field("position"),
+                        effect: field("effect"),
+                        notes: field("notes"),
+                    },
+                }),
+                Err(_) => {
+                    errors.push(ScriptError::Parse { lineno, error: "Invalid firing channel" });
+                    continue;
+                },
+            },
+        };
+
+        rows.push(PatchRow { lineno, board_id, mac_address, channel });
+    }
+
+    if errors.is_empty() {
+        Ok(rows)
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_patch, split_csv, ChannelInfo, PatchChannel, PatchRow};
+
+    #[test]
+    fn csv_fields() {
+        assert_eq!(split_csv("a, b ,,c").unwrap(), vec!["a", "b", "", "c"]);
+        assert_eq!(split_csv("\"a, b\",\"say \"\"hi\"\"\"").unwrap(),
+                   vec!["a, b", "say \"hi\""]);
+        assert_eq!(split_csv("\"a"), None);
+    }
+
+    #[test]
+    fn rows() {
+        let patch = "
+# Rig for the bonfire
+Channel,Board,Number,MAC,Effect,Notes,Extra,Igniter
+sparklers,001,1,d8:80:39:e0:7d:37,Fountain,\"Lit first, by hand\",x,ematch
+,002,,d8:80:39:e0:81:a8
+";
+        let rows = read_patch(patch.as_bytes()).unwrap();
+        assert_eq!(rows, vec![
+            PatchRow {
+                lineno: 4,
+                board_id: "001".to_string(),
+                mac_address: Some([0xd8, 0x80, 0x39, 0xe0, 0x7d, 0x37]),
+                channel: Some(PatchChannel {
+                    name: "sparklers".to_string(),
+                    num: 1,
+                    igniter: Some("ematch".to_string()),
+                    info: ChannelInfo {
+                        position: "".to_string(),
+                        effect: "Fountain".to_string(),
+                        notes: "Lit first, by hand".to_string(),
+                    },
+                }),
+            },
+            PatchRow {
+                lineno: 5,
+                board_id: "002".to_string(),
+                mac_address: Some([0xd8, 0x80, 0x39, 0xe0, 0x81, 0xa8]),
+                channel: None,
+            },
+        ]);
+    }
+
+    #[test]
+    #[should_panic(expected="Patch header must have")]
+    fn missing_columns() {
+        read_patch("board,channel\n001,ch1\n".as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn row_errors() {
+        let patch = "board,mac,channel,number\n001,zz,ch1,1\n001,,ch2,x\n,,ch3,3\n";
+        let errors: Vec<String> = read_patch(patch.as_bytes()).unwrap_err()
+                                                              .iter()
+                                                              .map(|e| e.to_string())
+                                                              .collect();
+        assert_eq!(errors, vec!["line 2: Invalid MAC address",
+                                "line 3: Invalid firing channel",
+                                "line 4: Missing board"]);
+    }
+}
@@ -1,9 +1,120 @@
 use std::io;
 use std::io::prelude::*;
+use std::thread;
 use std::str;
 use std::fmt;
-use std::net::{Ipv4Addr, TcpStream, UdpSocket};
+use std::error;
+use std::net::{Ipv4Addr, SocketAddr, TcpStream, UdpSocket};
 use std::time::{Instant, Duration};
+use std::sync::{Arc, Mutex, MutexGuard, Once};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::process;
+use std::cmp;
+
+use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook::iterator::Signals;
+
+#[derive(Debug)]
+pub enum PortfireError {
+    /// Could not open a connection to the board.
+    Connect(io::Error),
+    /// The board did not respond in time.
+    Timeout,
+    /// The board closed the connection before sending a complete response.
+    ShortRead { expected: usize, received: usize },
+    /// The board sent a response that was not understood.
+    UnexpectedReply(Vec<u8>),
+    /// The board understood the command but refused to carry it out.
+    Refused,
+    /// Any other IO error while talking to the board.
+    Io(io::Error),
+}
+
+impl From<io::Error> for PortfireError {
+    fn from(err: io::Error) -> PortfireError {
+        match err.kind() {
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => PortfireError::Timeout,
+            _ => PortfireError::Io(err),
+        }
+    }
+}
+
+impl fmt::Display for PortfireError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PortfireError::Connect(ref e) => write!(f, "could not connect: {}", e),
+            PortfireError::Timeout => write!(f, "timed out waiting for response"),
+            PortfireError::ShortRead { expected, received } =>
+                write!(f, "short response: expected {} bytes, got {}", expected, received),
+            PortfireError::UnexpectedReply(ref bytes) =>
+                write!(f, "unexpected response {:?}", bytes),
+            PortfireError::Refused => write!(f, "command refused by board"),
+            PortfireError::Io(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl error::Error for PortfireError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            PortfireError::Connect(ref e) | PortfireError::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+pub type PortfireResult<T> = Result<T, PortfireError>;
+
+/// How hard to try when firing a board that isn't responding.
+///
+/// Attempts stop once `max_attempts` have been made or once another attempt
+/// could not start before `deadline` has passed since the first, whichever
+/// comes first.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    pub max_attempts: usize,
+    pub deadline: Duration,
+    /// Time to wait after a failed attempt before trying again.
+    pub backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 5,
+            deadline: Duration::from_secs(3),
+            backoff: Duration::from_millis(100),
+        }
+    }
+}
+
+/// The result of trying to fire a board under a `RetryPolicy`.
+#[derive(Debug)]
+pub enum FireOutcome {
+    /// Fired on the first attempt.
+    Fired,
+    /// Fired, but only after retrying, `late` after the first attempt began.
+    Late { attempts: usize, late: Duration },
+    /// Gave up, with the error from the last attempt.
+    Failed { attempts: usize, error: PortfireError },
+}
+
+impl FireOutcome {
+    /// Whether the board acknowledged the fire command.
+    pub fn fired(&self) -> bool {
+        !matches!(*self, FireOutcome::Failed { .. })
+    }
+}
+
+/// The result of firing several boards at once with `fire_parallel`.
+#[derive(Debug)]
+pub struct ParallelFireReport {
+    /// The outcome for each board, in the order they were given.
+    pub outcomes: Vec<FireOutcome>,
+    /// Time between the first and last board acknowledging the fire command,
+    /// or None if no board fired.
+    pub skew: Option<Duration>,
+}
 
 #[derive(Debug,PartialEq,Clone)]
 pub struct Board {
@@ -35,6 +146,31 @@ pub fn autodiscover() -> io::Result<Vec<Board>> {
     Ok(boards)
 }
 
+/// Arm and fire several boards concurrently, each under `policy`, so that
+/// channels on different boards go off together.
+pub fn fire_parallel(fires: &[(&Client, [u8; 3])], policy: &RetryPolicy) -> ParallelFireReport {
+    let results: Vec<(FireOutcome, Instant)> = thread::scope(|scope| {
+        let handles: Vec<_> = fires.iter().map(|&(client, channels)| {
+            scope.spawn(move || {
+                let outcome = client.fire_retry(channels, policy);
+                (outcome, Instant::now())
+            })
+        }).collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+
+    let acks: Vec<Instant> = results.iter()
+                                    .filter(|&(outcome, _)| outcome.fired())
+                                    .map(|&(_, at)| at)
+                                    .collect();
+    let skew = match (acks.iter().min(), acks.iter().max()) {
+        (Some(first), Some(last)) => Some(last.duration_since(*first)),
+        _ => None,
+    };
+
+    ParallelFireReport { outcomes: results.into_iter().map(|(o, _)| o).collect(), skew }
+}
+
 impl Board {
     fn from_packet(buf: &[u8]) -> Option<Board> {
         if buf.len() < 18 {
@@ -49,69 +185,250 @@ impl Board {
         let ip = Ipv4Addr::new(buf[8], buf[9], buf[10], buf[11]);
         let mac = [buf[12], buf[13], buf[14], buf[15], buf[16], buf[17]];
 
-        Some(Board { ip: ip, mac: mac })
+        Some(Board { ip, mac })
+    }
+
+    /// Open a persistent session to this board.
+    pub fn client(&self) -> Client {
+        Client::new(self.clone())
+    }
+
+    // The methods below each use a fresh connection which is closed again
+    // afterwards. Use a `Client` to keep the connection open between commands.
+
+    pub fn ping(&self) -> PortfireResult<()> {
+        self.client().ping()
+    }
+
+    pub fn arm(&self) -> PortfireResult<()> {
+        self.client().arm()
+    }
+
+    pub fn disarm(&self) -> PortfireResult<()> {
+        self.client().disarm()
+    }
+
+    pub fn fire(&self, channels: [u8; 3]) -> PortfireResult<()> {
+        self.client().fire(channels)
+    }
+
+    pub fn fire_retry(&self, channels: [u8; 3], policy: &RetryPolicy) -> FireOutcome {
+        self.client().fire_retry(channels, policy)
+    }
+
+    pub fn bus_voltage(&self) -> PortfireResult<f32> {
+        self.client().bus_voltage()
     }
 
-    fn txrx(&self, cmd: &[u8], buf: &mut [u8]) -> io::Result<usize> {
-        let mut stream = TcpStream::connect((self.ip, 9090))?;
-        stream.set_nodelay(true)?;
-        stream.set_read_timeout(Some(Duration::from_millis(1500)))?;
-        stream.set_write_timeout(Some(Duration::from_millis(100)))?;
-        let _ = stream.write(cmd)?;
-        stream.read(buf)
+    pub fn continuities(&self) -> PortfireResult<[u8; 31]> {
+        self.client().continuities()
     }
+}
+
+/// A session with a single board, keeping its TCP connection open between
+/// commands and reconnecting whenever the connection is found to be broken.
+#[derive(Debug)]
+pub struct Client {
+    pub board: Board,
+    stream: Mutex<Option<TcpStream>>,
+}
+
+const CONNECT_TIMEOUT: Duration = Duration::from_millis(500);
+const READ_TIMEOUT: Duration = Duration::from_millis(1500);
+const WRITE_TIMEOUT: Duration = Duration::from_millis(100);
 
-    fn txrx_ok(&self, cmd: &[u8]) -> io::Result<()> {
-        let mut buf = [0u8; 4];
-        self.txrx(cmd, &mut buf)?;
-        if buf[0] == 'O' as u8 && buf[1] == 'K' as u8 {
-            Ok(())
-        } else {
-            Err(io::Error::new(io::ErrorKind::Other, "Invalid response"))
+/// `timeout`, shortened if need be so that it ends by `deadline`.
+fn limit(timeout: Duration, deadline: Option<Instant>) -> PortfireResult<Duration> {
+    match deadline.map(|d| d.saturating_duration_since(Instant::now())) {
+        None => Ok(timeout),
+        Some(left) if left > Duration::ZERO => Ok(cmp::min(timeout, left)),
+        Some(_) => Err(PortfireError::Timeout),
+    }
+}
+
+fn set_timeouts(stream: &TcpStream, deadline: Option<Instant>) -> PortfireResult<()> {
+    stream.set_read_timeout(Some(limit(READ_TIMEOUT, deadline)?))?;
+    stream.set_write_timeout(Some(limit(WRITE_TIMEOUT, deadline)?))?;
+    Ok(())
+}
+
+fn connect(ip: Ipv4Addr, deadline: Option<Instant>) -> PortfireResult<TcpStream> {
+    let addr = SocketAddr::from((ip, 9090));
+    let stream = TcpStream::connect_timeout(&addr, limit(CONNECT_TIMEOUT, deadline)?)
+                           .map_err(PortfireError::Connect)?;
+    stream.set_nodelay(true)?;
+    Ok(stream)
+}
+
+/// Whether a connection left open since the last command can still be used.
+/// The board may have closed it in the meantime, and anything already
+/// waiting to be read would be mistaken for the next response.
+fn is_usable(stream: &TcpStream) -> bool {
+    if stream.set_nonblocking(true).is_err() {
+        return false;
+    }
+    let usable = match stream.peek(&mut [0u8; 1]) {
+        Err(ref e) => e.kind() == io::ErrorKind::WouldBlock,
+        Ok(_) => false,
+    };
+    stream.set_nonblocking(false).is_ok() && usable
+}
+
+fn receive(stream: &mut TcpStream, buf: &mut [u8]) -> PortfireResult<()> {
+    // Keep reading until the expected response length has arrived.
+    let mut received = 0;
+    while received < buf.len() {
+        match stream.read(&mut buf[received..])? {
+            0 => return Err(PortfireError::ShortRead { expected: buf.len(), received }),
+            n => received += n,
         }
     }
+    Ok(())
+}
 
-    pub fn ping(&self) -> io::Result<()> {
-        let cmd = ['p' as u8];
-        self.txrx_ok(&cmd)
+impl Client {
+    /// Create a client for `board`. No connection is made until the first
+    /// command is sent.
+    pub fn new(board: Board) -> Client {
+        Client { board, stream: Mutex::new(None) }
     }
 
-    pub fn arm(&self) -> io::Result<()> {
-        let cmd = ['a' as u8];
-        self.txrx_ok(&cmd)
+    /// Close the connection to the board, if one is open.
+    pub fn disconnect(&self) {
+        *self.lock_stream() = None;
     }
 
-    pub fn disarm(&self) -> io::Result<()> {
-        let cmd = ['d' as u8];
-        self.txrx_ok(&cmd)
+    fn lock_stream(&self) -> MutexGuard<'_, Option<TcpStream>> {
+        // A thread which panicked mid-command may have left a half-read
+        // response on the connection, so start afresh. Disarming must still
+        // work while unwinding from such a panic.
+        self.stream.lock().unwrap_or_else(|poisoned| {
+            let mut stream = poisoned.into_inner();
+            *stream = None;
+            stream
+        })
     }
 
-    pub fn fire(&self, channels: [u8; 3]) -> io::Result<()> {
-        let cmd = ['f' as u8, channels[0], channels[1], channels[2]];
-        self.txrx_ok(&cmd)
+    /// Send `cmd` and read the response into `buf`, giving up at `deadline`
+    /// if one is given.
+    fn txrx(&self, cmd: &[u8], buf: &mut [u8], deadline: Option<Instant>) -> PortfireResult<()> {
+        let mut stream = self.lock_stream();
+
+        // Reuse the existing connection if the board hasn't closed it. If
+        // sending the command on it fails then nothing reached the board, so
+        // it's safe to reconnect and send it again. Once the command has been
+        // sent it is never repeated, since the board may already have acted
+        // on it; the connection is dropped if the response doesn't arrive.
+        let reused = stream.take().filter(is_usable);
+        if let Some(ref s) = reused {
+            set_timeouts(s, deadline)?;
+        }
+        let reused = reused.and_then(|mut s| s.write_all(cmd).ok().map(|_| s));
+        let mut s = match reused {
+            Some(s) => s,
+            None => {
+                let mut s = connect(self.board.ip, deadline)?;
+                set_timeouts(&s, deadline)?;
+                s.write_all(cmd)?;
+                s
+            },
+        };
+        receive(&mut s, buf)?;
+        *stream = Some(s);
+        Ok(())
     }
 
-    pub fn fire_retry(&self, channels: [u8; 3]) {
-        while let Err(_) = self.arm() {
-            println!("ERROR: Retrying arming command to board {}", self.ip);
+    fn txrx_ok(&self, cmd: &[u8], deadline: Option<Instant>) -> PortfireResult<()> {
+        let mut buf = [0u8; 2];
+        self.txrx(cmd, &mut buf, deadline)?;
+        match &buf {
+            b"OK" => Ok(()),
+            b"ER" => Err(PortfireError::Refused),
+            _ => {
+                // We can't trust what else might be waiting on this
+                // connection, so start afresh next time.
+                self.disconnect();
+                Err(PortfireError::UnexpectedReply(buf.to_vec()))
+            },
         }
-        while let Err(_) = self.fire(channels) {
-            println!("ERROR: Retrying firing command to board {}", self.ip);
-            while let Err(_) = self.arm() {}
+    }
+
+    pub fn ping(&self) -> PortfireResult<()> {
+        let cmd = [b'p'];
+        self.txrx_ok(&cmd, None)
+    }
+
+    /// Arm the board. Once the process has started disarming every board
+    /// on a signal, arming is always refused.
+    pub fn arm(&self) -> PortfireResult<()> {
+        self.arm_by(None)
+    }
+
+    fn arm_by(&self, deadline: Option<Instant>) -> PortfireResult<()> {
+        if SHUTTING_DOWN.load(Ordering::SeqCst) {
+            return Err(PortfireError::Refused);
+        }
+        let cmd = [b'a'];
+        self.txrx_ok(&cmd, deadline)
+    }
+
+    pub fn disarm(&self) -> PortfireResult<()> {
+        let cmd = [b'd'];
+        self.txrx_ok(&cmd, None)
+    }
+
+    pub fn fire(&self, channels: [u8; 3]) -> PortfireResult<()> {
+        self.fire_by(channels, None)
+    }
+
+    fn fire_by(&self, channels: [u8; 3], deadline: Option<Instant>) -> PortfireResult<()> {
+        let cmd = [b'f', channels[0], channels[1], channels[2]];
+        self.txrx_ok(&cmd, deadline)
+    }
+
+    /// Arm and fire, retrying both according to `policy`. The fire command is
+    /// only sent again if the board refused it or it never reached the board,
+    /// as otherwise the board may already have fired. No attempt is left
+    /// waiting on the board beyond the policy's deadline.
+    pub fn fire_retry(&self, channels: [u8; 3], policy: &RetryPolicy) -> FireOutcome {
+        let start = Instant::now();
+        let deadline = Some(start + policy.deadline);
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let error = match self.arm_by(deadline) {
+                Err(error) => error,
+                Ok(()) => match self.fire_by(channels, deadline) {
+                    Ok(()) => if attempts == 1 {
+                        return FireOutcome::Fired;
+                    } else {
+                        return FireOutcome::Late { attempts, late: start.elapsed() };
+                    },
+                    Err(error @ PortfireError::Refused) |
+                    Err(error @ PortfireError::Connect(_)) => error,
+                    Err(error) => return FireOutcome::Failed { attempts, error },
+                },
+            };
+            if attempts >= policy.max_attempts
+               || start.elapsed() + policy.backoff >= policy.deadline
+            {
+                return FireOutcome::Failed { attempts, error };
+            }
+            thread::sleep(policy.backoff);
         }
     }
 
-    pub fn bus_voltage(&self) -> io::Result<f32> {
+    pub fn bus_voltage(&self) -> PortfireResult<f32> {
         let mut buf = [0u8; 2];
-        let cmd = ['b' as u8];
-        self.txrx(&cmd, &mut buf)?;
+        let cmd = [b'b'];
+        self.txrx(&cmd, &mut buf, None)?;
         Ok((((buf[0] as u16) | ((buf[1] as u16)<<8)) as f32)/1000.0)
     }
 
-    pub fn continuities(&self) -> io::Result<[u8; 31]> {
+    pub fn continuities(&self) -> PortfireResult<[u8; 31]> {
         let mut buf = [0u8; 31];
-        let cmd = ['c' as u8];
-        self.txrx(&cmd, &mut buf)?;
+        let cmd = [b'c'];
+        self.txrx(&cmd, &mut buf, None)?;
         Ok(buf)
     }
 }
@@ -123,3 +440,252 @@ impl fmt::Display for Board {
                self.ip)
     }
 }
+
+/// Set once a signal has been received and every session is being disarmed.
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
+/// Clients of every live `ArmedSession`, by session ID, for the signal
+/// handler to disarm.
+static SESSIONS: Mutex<Vec<(usize, Vec<Arc<Client>>)>> = Mutex::new(Vec::new());
+static NEXT_SESSION_ID: AtomicUsize = AtomicUsize::new(0);
+static SIGNAL_HANDLER: Once = Once::new();
+
+/// Start a thread which disarms the boards of every live session and then
+/// exits when the process receives SIGINT or SIGTERM.
+fn install_signal_handler() {
+    SIGNAL_HANDLER.call_once(|| {
+        let mut signals = match Signals::new([SIGINT, SIGTERM]) {
+            Ok(signals) => signals,
+            Err(e) => {
+                eprintln!("Could not install signal handler, boards will not be \
+                           disarmed on Ctrl-C: {}", e);
+                return;
+            },
+        };
+        thread::spawn(move || {
+            if let Some(signal) = signals.forever().next() {
+                SHUTTING_DOWN.store(true, Ordering::SeqCst);
+                let sessions = SESSIONS.lock().unwrap_or_else(|e| e.into_inner());
+                for (_, clients) in sessions.iter() {
+                    for client in clients {
+                        match client.disarm() {
+                            Ok(()) => eprintln!("{} disarmed", client.board),
+                            Err(e) => eprintln!("{} disarm failed: {}", client.board, e),
+                        }
+                    }
+                }
+                process::exit(128 + signal);
+            }
+        });
+    });
+}
+
+/// A set of boards which may be armed, and which are all disarmed when the
+/// session is dropped, including while unwinding from a panic, or when the
+/// process receives SIGINT (as on Ctrl-C) or SIGTERM.
+///
+/// Note that `process::exit` does not run destructors, so call `disarm_all`
+/// before exiting that way.
+#[derive(Debug)]
+pub struct ArmedSession {
+    id: usize,
+    clients: Vec<Arc<Client>>,
+}
+
+impl ArmedSession {
+    /// Start guarding `clients`. Nothing is armed until asked.
+    pub fn new(clients: Vec<Arc<Client>>) -> ArmedSession {
+        install_signal_handler();
+        let id = NEXT_SESSION_ID.fetch_add(1, Ordering::SeqCst);
+        SESSIONS.lock().unwrap_or_else(|e| e.into_inner()).push((id, clients.clone()));
+        ArmedSession { id, clients }
+    }
+
+    pub fn clients(&self) -> &[Arc<Client>] {
+        &self.clients
+    }
+
+    /// Disarm every board in the session, returning the result for each.
+    pub fn disarm_all(&self) -> Vec<(Board, PortfireResult<()>)> {
+        self.clients.iter().map(|c| (c.board.clone(), c.disarm())).collect()
+    }
+}
+
+impl Drop for ArmedSession {
+    fn drop(&mut self) {
+        for (board, result) in self.disarm_all() {
+            if let Err(e) = result {
+                eprintln!("{} disarm failed: {}", board, e);
+            }
+        }
+        SESSIONS.lock().unwrap_or_else(|e| e.into_inner()).retain(|&(id, _)| id != self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ArmedSession, Board, FireOutcome, PortfireError, RetryPolicy};
+    use simulator::SimBoard;
+    use std::net::Ipv4Addr;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn client_keeps_connection() {
+        let sim = SimBoard::start(Ipv4Addr::new(127, 0, 1, 7), [0, 0, 0, 0, 1, 7]).unwrap();
+        let client = sim.board().client();
+        client.ping().unwrap();
+        client.arm().unwrap();
+        client.bus_voltage().unwrap();
+        client.continuities().unwrap();
+        assert_eq!(sim.state().connections, 1);
+    }
+
+    #[test]
+    fn client_reconnects() {
+        let sim = SimBoard::start(Ipv4Addr::new(127, 0, 1, 8), [0, 0, 0, 0, 1, 8]).unwrap();
+        let client = sim.board().client();
+        client.arm().unwrap();
+        sim.disconnect_all();
+        client.fire([3, 0, 0]).unwrap();
+        assert_eq!(sim.state().connections, 2);
+        assert_eq!(sim.fired_channels(), vec![3]);
+    }
+
+    #[test]
+    fn client_never_resends_fire() {
+        let sim = SimBoard::start(Ipv4Addr::new(127, 0, 1, 24), [0, 0, 0, 0, 1, 24]).unwrap();
+        let client = sim.board().client();
+        client.arm().unwrap();
+        sim.state().drop_next_fire = 1;
+        match client.fire([4, 0, 0]) {
+            Err(PortfireError::Timeout) => {},
+            r => panic!("expected Timeout, got {:?}", r),
+        }
+        assert_eq!(sim.state().fired, vec![[4, 0, 0]]);
+
+        // Nor does retrying, once the fire command has reached the board.
+        sim.state().drop_next_fire = 1;
+        match client.fire_retry([5, 0, 0], &RetryPolicy::default()) {
+            FireOutcome::Failed { attempts: 1, error: PortfireError::Timeout } => {},
+            r => panic!("expected Failed after 1 attempt, got {:?}", r),
+        }
+        assert_eq!(sim.fired_channels(), vec![4, 5]);
+
+        client.ping().unwrap();
+        assert_eq!(sim.state().connections, 3);
+    }
+
+    #[test]
+    fn connect_error() {
+        let board = Board { ip: Ipv4Addr::new(127, 0, 1, 6), mac: [0, 0, 0, 0, 1, 6] };
+        match board.ping() {
+            Err(PortfireError::Connect(_)) => {},
+            r => panic!("expected Connect, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn fire_retry() {
+        let sim = SimBoard::start(Ipv4Addr::new(127, 0, 1, 9), [0, 0, 0, 0, 1, 9]).unwrap();
+        let client = sim.board().client();
+        match client.fire_retry([1, 0, 0], &RetryPolicy::default()) {
+            FireOutcome::Fired => {},
+            r => panic!("expected Fired, got {:?}", r),
+        }
+        sim.state().refuse_next = 2;
+        match client.fire_retry([2, 0, 0], &RetryPolicy::default()) {
+            FireOutcome::Late { attempts: 3, .. } => {},
+            r => panic!("expected Late after 3 attempts, got {:?}", r),
+        }
+        assert_eq!(sim.fired_channels(), vec![1, 2]);
+    }
+
+    #[test]
+    fn fire_retry_max_attempts() {
+        let sim = SimBoard::start(Ipv4Addr::new(127, 0, 1, 10), [0, 0, 0, 0, 1, 10]).unwrap();
+        sim.state().refuse_next = 100;
+        let policy = RetryPolicy { max_attempts: 3, backoff: Duration::from_millis(1),
+                                   ..RetryPolicy::default() };
+        match sim.board().client().fire_retry([1, 0, 0], &policy) {
+            FireOutcome::Failed { attempts: 3, error: PortfireError::Refused } => {},
+            r => panic!("expected Failed after 3 attempts, got {:?}", r),
+        }
+        assert!(sim.fired_channels().is_empty());
+    }
+
+    #[test]
+    fn fire_retry_deadline() {
+        let board = Board { ip: Ipv4Addr::new(127, 0, 1, 11), mac: [0, 0, 0, 0, 1, 11] };
+        let policy = RetryPolicy { max_attempts: 1000, deadline: Duration::from_millis(100),
+                                   backoff: Duration::from_millis(20) };
+        let start = Instant::now();
+        match board.fire_retry([1, 0, 0], &policy) {
+            FireOutcome::Failed { error: PortfireError::Connect(_), .. } => {},
+            r => panic!("expected Failed, got {:?}", r),
+        }
+        assert!(start.elapsed() < Duration::from_millis(500));
+    }
+
+    #[test]
+    fn fire_retry_deadline_unresponsive() {
+        let sim = SimBoard::start(Ipv4Addr::new(127, 0, 1, 25), [0, 0, 0, 0, 1, 25]).unwrap();
+        sim.state().silent = true;
+        let policy = RetryPolicy { max_attempts: 1000, deadline: Duration::from_millis(200),
+                                   backoff: Duration::from_millis(20) };
+        let start = Instant::now();
+        match sim.board().client().fire_retry([1, 0, 0], &policy) {
+            FireOutcome::Failed { error: PortfireError::Timeout, .. } => {},
+            r => panic!("expected Failed, got {:?}", r),
+        }
+        assert!(start.elapsed() < Duration::from_millis(500));
+        assert!(sim.fired_channels().is_empty());
+    }
+
+    #[test]
+    fn fire_parallel() {
+        let sim1 = SimBoard::start(Ipv4Addr::new(127, 0, 1, 12), [0, 0, 0, 0, 1, 12]).unwrap();
+        let sim2 = SimBoard::start(Ipv4Addr::new(127, 0, 1, 13), [0, 0, 0, 0, 1, 13]).unwrap();
+        let dead = Board { ip: Ipv4Addr::new(127, 0, 1, 14), mac: [0, 0, 0, 0, 1, 14] };
+        let (client1, client2, client3) = (sim1.board().client(), sim2.board().client(),
+                                           dead.client());
+        let policy = RetryPolicy { max_attempts: 2, ..RetryPolicy::default() };
+
+        let report = super::fire_parallel(&[(&client1, [1, 2, 0]), (&client2, [5, 0, 0]),
+                                               (&client3, [1, 0, 0])], &policy);
+        assert_eq!(report.outcomes.len(), 3);
+        assert!(report.outcomes[0].fired());
+        assert!(report.outcomes[1].fired());
+        assert!(!report.outcomes[2].fired());
+        assert!(report.skew.is_some());
+        assert_eq!(sim1.fired_channels(), vec![1, 2]);
+        assert_eq!(sim2.fired_channels(), vec![5]);
+
+        let report = super::fire_parallel(&[(&client3, [2, 0, 0])], &policy);
+        assert_eq!(report.skew, None);
+    }
+
+    #[test]
+    fn armed_session_disarms_on_drop() {
+        let sim = SimBoard::start(Ipv4Addr::new(127, 0, 1, 15), [0, 0, 0, 0, 1, 15]).unwrap();
+        let session = ArmedSession::new(vec![Arc::new(sim.board().client())]);
+        session.clients()[0].arm().unwrap();
+        assert!(sim.state().armed);
+        drop(session);
+        assert!(!sim.state().armed);
+    }
+
+    #[test]
+    fn armed_session_disarms_on_panic() {
+        let sim = SimBoard::start(Ipv4Addr::new(127, 0, 1, 16), [0, 0, 0, 0, 1, 16]).unwrap();
+        let board = sim.board();
+        let result = thread::spawn(move || {
+            let session = ArmedSession::new(vec![Arc::new(board.client())]);
+            session.clients()[0].arm().unwrap();
+            panic!("show failed");
+        }).join();
+        assert!(result.is_err());
+        assert!(!sim.state().armed);
+    }
+}
@@ -1,9 +1,19 @@
 use std::io;
+use std::fmt;
+use std::error;
 use std::io::prelude::*;
 use std::io::BufReader;
-use std::path::Path;
-use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::fs::{self, File};
 use std::collections::HashMap;
+use std::time::Duration;
+
+use audacity;
+use checks::{ThresholdName, OPEN};
+use patch::{self, ChannelInfo, PatchRow};
+
+use serde::{Deserialize, Serialize};
+use serde_json;
 
 #[derive(Debug)]
 pub enum ScriptError {
@@ -12,12 +22,22 @@ pub enum ScriptError {
     DuplicateBoardId { lineno: usize, board_id: String },
     DuplicateBoardMac { lineno: usize, mac_address: [u8; 6] },
     DuplicateChannel { lineno: usize, name: String },
+    DuplicateGroup { lineno: usize, name: String },
     InvalidChannelNum { lineno: usize, num: u8 },
     UnknownBoardId { lineno: usize, board_id: String },
     ChannelRefired { lineno: usize, channel: String },
     UndefinedChannel { lineno: usize, channel: String },
-    ExcessChannelsPerBoard { lineno: usize, board_id: String },
     FireWithoutSleep { lineno: usize },
+    NonMonotonicTime { lineno: usize, time: Duration },
+    DuplicateThreshold { lineno: usize, name: ThresholdName },
+    DuplicateIgniter { lineno: usize, name: String },
+    UnknownIgniter { lineno: usize, name: String },
+    IncludeFailed { lineno: usize, path: PathBuf, error: io::Error },
+    IncludeCycle { lineno: usize, path: PathBuf },
+    /// An error in an included file, with line numbers relative to that file.
+    Included { path: PathBuf, error: Box<ScriptError> },
+    /// A show in JSON format could not be read.
+    Json(serde_json::Error),
 }
 
 impl From<io::Error> for ScriptError {
@@ -30,78 +50,494 @@ pub type ScriptResult<T> = Result<T, ScriptError>;
 
 impl ScriptError {
     fn parse_err<T>(lineno: usize, error: &'static str) -> ScriptResult<T> {
-        Err(ScriptError::Parse { lineno: lineno, error: error })
+        Err(ScriptError::Parse { lineno, error })
     }
 
     fn parse_err_numargs<T>(lineno: usize) -> ScriptResult<T> {
-        Err(ScriptError::Parse { lineno: lineno,
+        Err(ScriptError::Parse { lineno,
                                  error: "Wrong number of arguments" })
     }
+
+    /// The script line number the error occurred on, if any. For errors in
+    /// included files, this is the line number within the included file.
+    pub fn lineno(&self) -> Option<usize> {
+        match *self {
+            ScriptError::Io(_) | ScriptError::Json(_) => None,
+            ScriptError::Included { ref error, .. } => error.lineno(),
+            ScriptError::Parse { lineno, .. } |
+            ScriptError::DuplicateBoardId { lineno, .. } |
+            ScriptError::DuplicateBoardMac { lineno, .. } |
+            ScriptError::DuplicateChannel { lineno, .. } |
+            ScriptError::DuplicateGroup { lineno, .. } |
+            ScriptError::InvalidChannelNum { lineno, .. } |
+            ScriptError::UnknownBoardId { lineno, .. } |
+            ScriptError::ChannelRefired { lineno, .. } |
+            ScriptError::UndefinedChannel { lineno, .. } |
+            ScriptError::FireWithoutSleep { lineno } |
+            ScriptError::NonMonotonicTime { lineno, .. } |
+            ScriptError::DuplicateThreshold { lineno, .. } |
+            ScriptError::DuplicateIgniter { lineno, .. } |
+            ScriptError::UnknownIgniter { lineno, .. } |
+            ScriptError::IncludeFailed { lineno, .. } |
+            ScriptError::IncludeCycle { lineno, .. } => Some(lineno),
+        }
+    }
+
+    /// The word on the offending line which caused the error, if any.
+    fn word(&self) -> Option<String> {
+        match *self {
+            ScriptError::DuplicateBoardId { ref board_id, .. } |
+            ScriptError::UnknownBoardId { ref board_id, .. } => Some(board_id.clone()),
+            ScriptError::DuplicateChannel { ref name, .. } |
+            ScriptError::DuplicateGroup { ref name, .. } => Some(name.clone()),
+            ScriptError::ChannelRefired { ref channel, .. } |
+            ScriptError::UndefinedChannel { ref channel, .. } => Some(channel.clone()),
+            ScriptError::InvalidChannelNum { num, .. } => Some(num.to_string()),
+            ScriptError::DuplicateThreshold { name, .. } => Some(name.to_string()),
+            ScriptError::DuplicateIgniter { ref name, .. } |
+            ScriptError::UnknownIgniter { ref name, .. } => Some(name.clone()),
+            _ => None,
+        }
+    }
+
+    /// Render the error followed by the offending line from `source`, with a
+    /// caret marking the word responsible, for example:
+    ///
+    /// ```text
+    /// line 6: channel 'ch7' is not defined
+    ///     fire ch1 ch7
+    ///              ^^^
+    /// ```
+    ///
+    /// For errors in included files, the line is read from the included file.
+    pub fn describe(&self, source: &str) -> String {
+        if let ScriptError::Included { ref path, ref error } = *self {
+            let source = fs::read_to_string(path).unwrap_or_default();
+            return format!("{}: {}", path.display(), error.describe(&source));
+        }
+
+        let line = match self.lineno().and_then(|n| source.lines().nth(n - 1)) {
+            Some(line) => line,
+            None => return self.to_string(),
+        };
+
+        // Point at the word responsible if we can find it on the line,
+        // otherwise at the command at the start of the line.
+        let mut words = line.split_whitespace()
+                            .map(|w| (w.as_ptr() as usize - line.as_ptr() as usize, w));
+        let command = words.next();
+        let target = match self.word() {
+            Some(word) => words.find(|&(_, w)| w == word).or(command),
+            None => command,
+        };
+        let (column, width) = match target {
+            Some((column, word)) => (column, word.chars().count()),
+            None => (0, 1),
+        };
+        let indent: String = line[..column].chars()
+                                           .map(|c| if c == '\t' { '\t' } else { ' ' })
+                                           .collect();
+
+        format!("{}\n    {}\n    {}{}", self, line, indent, "^".repeat(width))
+    }
 }
 
-#[derive(Debug, PartialEq)]
+/// Format a show time as "mm:ss.sss", or "hh:mm:ss.sss" for times of an hour
+/// or more. This is the same format accepted by "at" cues.
+pub fn format_timecode(time: Duration) -> String {
+    let millis = time.as_millis();
+    let (hours, minutes) = (millis / 3_600_000, (millis / 60_000) % 60);
+    let (secs, millis) = ((millis / 1000) % 60, millis % 1000);
+    if hours > 0 {
+        format!("{:02}:{:02}:{:02}.{:03}", hours, minutes, secs, millis)
+    } else {
+        format!("{:02}:{:02}.{:03}", minutes, secs, millis)
+    }
+}
+
+/// Format a duration as a number of seconds, exactly and without trailing
+/// zeros, such as "5", "0.25" or "1.000000001".
+pub fn format_duration(time: Duration) -> String {
+    match time.subsec_nanos() {
+        0 => time.as_secs().to_string(),
+        nanos => format!("{}.{}", time.as_secs(),
+                         format!("{:09}", nanos).trim_end_matches('0')),
+    }
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ScriptError::Io(ref e) => write!(f, "{}", e),
+            ScriptError::Parse { lineno, error } =>
+                write!(f, "line {}: {}", lineno, error),
+            ScriptError::DuplicateBoardId { lineno, ref board_id } =>
+                write!(f, "line {}: board '{}' is already defined", lineno, board_id),
+            ScriptError::DuplicateBoardMac { lineno, mac_address: m } =>
+                write!(f, "line {}: MAC address {:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x} \
+                           is already used by another board",
+                       lineno, m[0], m[1], m[2], m[3], m[4], m[5]),
+            ScriptError::DuplicateChannel { lineno, ref name } =>
+                write!(f, "line {}: channel '{}' is already defined, or its board channel \
+                           number is already in use", lineno, name),
+            ScriptError::DuplicateGroup { lineno, ref name } =>
+                write!(f, "line {}: group '{}' has the same name as an existing channel \
+                           or group", lineno, name),
+            ScriptError::InvalidChannelNum { lineno, num } =>
+                write!(f, "line {}: channel number {} is not between 1 and 30", lineno, num),
+            ScriptError::UnknownBoardId { lineno, ref board_id } =>
+                write!(f, "line {}: board '{}' is not defined", lineno, board_id),
+            ScriptError::ChannelRefired { lineno, ref channel } =>
+                write!(f, "line {}: channel '{}' has already been fired", lineno, channel),
+            ScriptError::UndefinedChannel { lineno, ref channel } =>
+                write!(f, "line {}: channel '{}' is not defined", lineno, channel),
+            ScriptError::FireWithoutSleep { lineno } =>
+                write!(f, "line {}: no sleep or pause since the previous fire", lineno),
+            ScriptError::NonMonotonicTime { lineno, time } =>
+                write!(f, "line {}: show time {} is earlier than the show time already \
+                           reached", lineno, format_timecode(time)),
+            ScriptError::DuplicateThreshold { lineno, name } =>
+                write!(f, "line {}: threshold '{}' is already set", lineno, name),
+            ScriptError::DuplicateIgniter { lineno, ref name } =>
+                write!(f, "line {}: igniter '{}' is already defined", lineno, name),
+            ScriptError::UnknownIgniter { lineno, ref name } =>
+                write!(f, "line {}: igniter '{}' is not defined", lineno, name),
+            ScriptError::IncludeFailed { lineno, ref path, ref error } =>
+                write!(f, "line {}: could not include '{}': {}", lineno, path.display(), error),
+            ScriptError::IncludeCycle { lineno, ref path } =>
+                write!(f, "line {}: '{}' is already being included", lineno, path.display()),
+            ScriptError::Included { ref path, ref error } =>
+                write!(f, "{}: {}", path.display(), error),
+            ScriptError::Json(ref e) => write!(f, "invalid JSON: {}", e),
+        }
+    }
+}
+
+impl error::Error for ScriptError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            ScriptError::Io(ref e) | ScriptError::IncludeFailed { error: ref e, .. } => Some(e),
+            ScriptError::Included { ref error, .. } => Some(error.as_ref()),
+            ScriptError::Json(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Cues are serialised tagged with their script command, with MAC addresses
+/// and times written as they would be in a script.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "cue", rename_all = "lowercase")]
 pub enum Cue {
-    Board { board_id: String, mac_address: [u8; 6] },
-    Channel { name: String, board_id: String, num: u8 },
+    Board {
+        board_id: String,
+        #[serde(with = "text::mac_address")]
+        mac_address: [u8; 6],
+    },
+    Channel {
+        name: String,
+        board_id: String,
+        num: u8,
+        /// The type of igniter expected on the channel, if given.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        igniter: Option<String>,
+    },
+    Group { name: String, channels: Vec<String> },
+    Threshold { name: ThresholdName, value: f32 },
+    /// A type of igniter, with the range of continuity readings expected
+    /// from a channel with one connected.
+    Igniter { name: String, min: u8, max: u8 },
     Fire { channels: Vec<String> },
     Say { message: String },
     Print { message: String },
-    Sleep { time: u64 },
+    Sleep {
+        #[serde(with = "text::duration")]
+        time: Duration,
+    },
+    FireGap {
+        #[serde(with = "text::duration")]
+        time: Duration,
+    },
+    At {
+        #[serde(with = "text::timecode")]
+        time: Duration,
+    },
     Pause,
 }
 
+/// Cues are displayed as the script line they were parsed from, in
+/// canonical form. An `At` cue is displayed alone, which is equivalent to
+/// following it with the next cue on the same line.
+impl fmt::Display for Cue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Cue::Board { ref board_id, mac_address: m } =>
+                write!(f, "board {} {:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+                       board_id, m[0], m[1], m[2], m[3], m[4], m[5]),
+            Cue::Channel { ref name, ref board_id, num, igniter: None } =>
+                write!(f, "channel {} {} {}", name, board_id, num),
+            Cue::Channel { ref name, ref board_id, num, igniter: Some(ref igniter) } =>
+                write!(f, "channel {} {} {} {}", name, board_id, num, igniter),
+            Cue::Group { ref name, ref channels } =>
+                write!(f, "group {} {}", name, channels.join(" ")),
+            Cue::Threshold { name, value } => write!(f, "threshold {} {}", name, value),
+            Cue::Igniter { ref name, min, max } => write!(f, "igniter {} {} {}", name, min, max),
+            Cue::Fire { ref channels } => write!(f, "fire {}", channels.join(" ")),
+            Cue::Say { ref message } if message.is_empty() => write!(f, "say"),
+            Cue::Say { ref message } => write!(f, "say {}", message),
+            Cue::Print { ref message } if message.is_empty() => write!(f, "print"),
+            Cue::Print { ref message } => write!(f, "print {}", message),
+            Cue::Sleep { time } => write!(f, "sleep {}", format_duration(time)),
+            Cue::FireGap { time } => write!(f, "firegap {}", format_duration(time)),
+            Cue::At { time } => {
+                // Show times are only given to the millisecond by
+                // format_timecode, so add any further digits.
+                let extra = format!("{:06}", time.subsec_nanos() % 1_000_000);
+                write!(f, "at {}{}", format_timecode(time), extra.trim_end_matches('0'))
+            },
+            Cue::Pause => write!(f, "pause"),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub struct Script {
     pub cues: Vec<Cue>,
+    /// The line each cue was read from.
+    pub lines: Vec<SourceLine>,
     pub boards: HashMap<String, [u8; 6]>,
     pub channels: HashMap<String, (String, u8)>,
-    pub duration: u64,
+    /// Each group name mapped to the channels it contains, with any groups
+    /// within it already expanded.
+    pub groups: HashMap<String, Vec<String>>,
+    /// Descriptions of channels loaded from patch files.
+    pub channel_info: HashMap<String, ChannelInfo>,
+    /// Board check thresholds set by the script, with the line each was set
+    /// on.
+    pub thresholds: HashMap<ThresholdName, (f32, SourceLine)>,
+    /// Igniter types defined by the script.
+    pub igniters: HashMap<String, Igniter>,
+    /// The igniter type given for each channel which has one.
+    pub channel_igniters: HashMap<String, String>,
+    pub duration: Duration,
+    /// Time added to `duration` by fire cues with more than three channels
+    /// on one board, which are sent as a sequence of fire commands.
+    pub sequencing: Duration,
+}
+
+/// A line of the script, or of a file it reads such as an included script,
+/// patch file or label track.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SourceLine {
+    /// The file the line is in, or None for the script itself.
+    pub path: Option<PathBuf>,
+    pub lineno: usize,
+}
+
+impl SourceLine {
+    /// A line of the script itself.
+    pub fn script(lineno: usize) -> SourceLine {
+        SourceLine { path: None, lineno }
+    }
+}
+
+impl fmt::Display for SourceLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.path {
+            Some(ref path) => write!(f, "line {} of {}", self.lineno, path.display()),
+            None => write!(f, "line {}", self.lineno),
+        }
+    }
+}
+
+/// A type of igniter defined by a script.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Igniter {
+    /// The lowest and highest continuity readings expected from a channel
+    /// with this igniter connected.
+    pub min: u8,
+    pub max: u8,
+    /// The line the igniter was defined on.
+    pub line: SourceLine,
+}
+
+/// Default time between successive fire commands to one board, when a fire
+/// cue has more than three of its channels.
+pub const DEFAULT_FIRE_GAP_MS: u64 = 100;
+
+/// Parse a colon-delimited MAC address such as "d8:80:39:e0:7d:37".
+pub fn parse_mac_address(mac: &str) -> Option<[u8; 6]> {
+    let octets: Vec<u8> = mac.split(':')
+                             .map(|x| u8::from_str_radix(x, 16).ok())
+                             .collect::<Option<Vec<u8>>>()?;
+    if octets.len() != 6 {
+        return None;
+    }
+    Some([octets[0], octets[1], octets[2], octets[3], octets[4], octets[5]])
+}
+
+/// Parse a time in seconds such as "5", "0.25" or "1.5s", or in milliseconds
+/// such as "250ms". Decimals are handled exactly, down to the nanosecond.
+pub fn parse_duration(time: &str) -> Option<Duration> {
+    let (number, scale) = if let Some(ms) = time.strip_suffix("ms") {
+        (ms, 1_000_000)
+    } else if let Some(s) = time.strip_suffix('s') {
+        (s, 1_000_000_000)
+    } else {
+        (time, 1_000_000_000)
+    };
+
+    let mut parts = number.splitn(2, '.');
+    let whole = parts.next().unwrap_or("");
+    let frac = parts.next().unwrap_or("");
+    if whole.is_empty() && frac.is_empty() {
+        return None;
+    }
+    if !whole.chars().chain(frac.chars()).all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let whole: u64 = if whole.is_empty() { 0 } else { whole.parse().ok()? };
+    let mut nanos = whole.checked_mul(scale)?;
+    let mut unit = scale;
+    for digit in frac.chars() {
+        unit /= 10;
+        nanos = nanos.checked_add(digit.to_digit(10)? as u64 * unit)?;
+    }
+    Some(Duration::from_nanos(nanos))
+}
+
+/// Parse a show time such as "01:23.500", "1:02:03" or "83.5" into a
+/// Duration. Minutes and seconds must be under 60 when preceded by a larger
+/// unit.
+fn parse_timecode(time: &str) -> Option<Duration> {
+    let fields: Vec<&str> = time.split(':').collect();
+    if fields.len() > 3 {
+        return None;
+    }
+
+    let (secs, larger) = fields.split_last()?;
+    if !secs.chars().all(|c| c.is_ascii_digit() || c == '.') {
+        return None;
+    }
+    let secs = parse_duration(secs)?;
+    if !larger.is_empty() && secs >= Duration::from_secs(60) {
+        return None;
+    }
+
+    let mut minutes: u64 = 0;
+    for (idx, field) in larger.iter().enumerate() {
+        if field.is_empty() || !field.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        let value: u64 = field.parse().ok()?;
+        if idx > 0 && value >= 60 {
+            return None;
+        }
+        minutes = minutes.checked_mul(60)?.checked_add(value)?;
+    }
+
+    Some(Duration::from_secs(minutes.checked_mul(60)?) + secs)
+}
+
+/// Serde field formats matching the script syntax.
+mod text {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use serde::de::Error;
+    use std::time::Duration;
+
+    pub mod mac_address {
+        use super::*;
+        use script::parse_mac_address;
+
+        pub fn serialize<S: Serializer>(m: &[u8; 6], s: S) -> Result<S::Ok, S::Error> {
+            s.serialize_str(&format!("{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+                                     m[0], m[1], m[2], m[3], m[4], m[5]))
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<[u8; 6], D::Error> {
+            let mac = String::deserialize(d)?;
+            parse_mac_address(&mac).ok_or_else(|| D::Error::custom("invalid MAC address"))
+        }
+    }
+
+    pub mod duration {
+        use super::*;
+        use script::{format_duration, parse_duration};
+
+        pub fn serialize<S: Serializer>(time: &Duration, s: S) -> Result<S::Ok, S::Error> {
+            s.serialize_str(&format_duration(*time))
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Duration, D::Error> {
+            let time = String::deserialize(d)?;
+            parse_duration(&time).ok_or_else(|| D::Error::custom("invalid time"))
+        }
+    }
+
+    pub mod timecode {
+        use super::*;
+        use script::{Cue, parse_timecode};
+
+        pub fn serialize<S: Serializer>(time: &Duration, s: S) -> Result<S::Ok, S::Error> {
+            let cue = Cue::At { time: *time }.to_string();
+            s.serialize_str(&cue["at ".len()..])
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Duration, D::Error> {
+            let time = String::deserialize(d)?;
+            parse_timecode(&time).ok_or_else(|| D::Error::custom("invalid show time"))
+        }
+    }
 }
 
 impl Cue {
-    fn from_line(line: &String, lineno: usize) -> ScriptResult<Option<Cue>> {
+    /// Parse one line of a script into its cues: none for a blank line or a
+    /// comment, and two for an `at` with a cue after it. The commands which
+    /// read other files are handled by the script parser, not here.
+    pub fn from_line(line: &str, lineno: usize) -> ScriptResult<Vec<Cue>> {
         let args: Vec<&str> = line.split_whitespace().collect();
         match args.first() {
             // Empty lines and comments are ignored
-            None | Some(&"#") => Ok(None),
+            None | Some(&"#") => Ok(vec![]),
 
             Some(&word) => match word {
 
                 // Parse a "say" command, where the entire rest of the line
                 // is the message to say.
-                "say" => {
-                    if args.len() < 2 {
-                        Ok(Some(Cue::Say { message: "".to_string() } ))
-                    } else {
-                        let (_, message) = line.split_at(4);
-                        Ok(Some(Cue::Say{ message: String::from(message) }))
-                    }
-                },
+                "say" => Ok(vec![Cue::Say { message: rest_of_line(line, word) }]),
 
                 // Parse a "print" command. Like a "say" command, the rest of
                 // the line is the message to print.
-                "print" => {
-                    if args.len() < 2 {
-                        Ok(Some(Cue::Print { message: "".to_string() }))
-                    } else {
-                        let (_, message) = line.split_at(6);
-                        Ok(Some(Cue::Print{ message: String::from(message) }))
-                    }
-                },
+                "print" => Ok(vec![Cue::Print { message: rest_of_line(line, word) }]),
 
-                // Parse a "sleep" command. The single argument must be an
-                // integer number of seconds to sleep.
+                // Parse a "sleep" command. The single argument is a time in
+                // seconds, which may be fractional, or in milliseconds with
+                // an "ms" suffix.
                 "sleep" => {
                     if args.len() != 2 {
                         return ScriptError::parse_err_numargs(lineno);
                     }
 
-                    match args[1].parse() {
-                        Ok(time) => Ok(Some(Cue::Sleep { time: time })),
-                        _ => ScriptError::parse_err(lineno, "Invalid sleep time")
+                    match parse_duration(args[1]) {
+                        Some(time) => Ok(vec![Cue::Sleep { time }]),
+                        None => ScriptError::parse_err(lineno, "Invalid sleep time")
                     }
                 }
 
+                // Parse a "firegap" command. The single argument is a time in
+                // the same format as for "sleep".
+                "firegap" => {
+                    if args.len() != 2 {
+                        return ScriptError::parse_err_numargs(lineno);
+                    }
+
+                    match parse_duration(args[1]) {
+                        Some(time) => Ok(vec![Cue::FireGap { time }]),
+                        None => ScriptError::parse_err(lineno, "Invalid fire gap time")
+                    }
+                },
+
                 // Parse a "board" command. There's a board_id and a
                 // colon-delimited MAC address.
                 "board" => {
@@ -109,38 +545,81 @@ impl Cue {
                         return ScriptError::parse_err_numargs(lineno);
                     }
 
-                    let octets: Vec<u8> = args[2].split(":")
-                                                 .filter_map(|x| u8::from_str_radix(x, 16).ok())
-                                                 .collect();
-                    if octets.len() != 6 {
-                        return ScriptError::parse_err(lineno, "Invalid MAC address")
+                    match parse_mac_address(args[2]) {
+                        Some(mac_address) => Ok(vec![Cue::Board {
+                            board_id: String::from(args[1]),
+                            mac_address
+                        }]),
+                        None => ScriptError::parse_err(lineno, "Invalid MAC address")
                     }
-
-                    Ok(Some(Cue::Board {
-                        board_id: String::from(args[1]),
-                        mac_address: [octets[0], octets[1], octets[2],
-                                      octets[3], octets[4], octets[5]]
-                    }))
                 },
 
                 // Parse a "channel" command. There's a channel name and a
-                // mapped board_id and channel_num.
+                // mapped board_id and channel_num, then optionally the type
+                // of igniter on the channel.
                 "channel" => {
-                    if args.len() != 4 {
+                    if args.len() != 4 && args.len() != 5 {
                         return ScriptError::parse_err_numargs(lineno);
                     }
 
                     match args[3].parse() {
-                        Ok(num) => Ok(Some(Cue::Channel {
+                        Ok(num) => Ok(vec![Cue::Channel {
                             name: String::from(args[1]),
                             board_id: String::from(args[2]),
-                            num: num
-                        })),
+                            num,
+                            igniter: args.get(4).map(|s| s.to_string()),
+                        }]),
                         _ => ScriptError::parse_err(lineno, "Invalid firing channel")
                     }
                 },
 
-                // Parse a "fire" command. Each argument is a firing channel name.
+                // Parse a "group" command. There's a group name and then one
+                // or more channel or group names which it contains.
+                "group" => {
+                    if args.len() < 3 {
+                        return ScriptError::parse_err_numargs(lineno);
+                    }
+
+                    let channels: Vec<String> = args[2..].iter().map(|s| s.to_string()).collect();
+
+                    Ok(vec![Cue::Group { name: String::from(args[1]), channels }])
+                },
+
+                // Parse an "igniter" command. There's the name of a type of
+                // igniter and the lowest and highest continuity readings
+                // expected from a channel with one connected.
+                "igniter" => {
+                    if args.len() != 4 {
+                        return ScriptError::parse_err_numargs(lineno);
+                    }
+
+                    match (args[2].parse(), args[3].parse()) {
+                        (Ok(min), Ok(max)) => Ok(vec![Cue::Igniter {
+                            name: String::from(args[1]), min, max
+                        }]),
+                        _ => ScriptError::parse_err(lineno, "Invalid resistance")
+                    }
+                },
+
+                // Parse a "threshold" command. There's the name of a board
+                // check threshold and its value.
+                "threshold" => {
+                    if args.len() != 3 {
+                        return ScriptError::parse_err_numargs(lineno);
+                    }
+
+                    let name = match ThresholdName::parse(args[1]) {
+                        Some(name) => name,
+                        None => return ScriptError::parse_err(lineno, "Invalid threshold name"),
+                    };
+                    match args[2].parse() {
+                        Ok(value) => Ok(vec![Cue::Threshold { name, value }]),
+                        _ => ScriptError::parse_err(lineno, "Invalid threshold value")
+                    }
+                },
+
+                // Parse a "fire" command. Each argument is a firing channel
+                // or group name.
                 "fire" => {
                     if args.len() < 2 {
                         return ScriptError::parse_err_numargs(lineno);
@@ -148,7 +627,7 @@ impl Cue {
 
                     let channels: Vec<String> = args[1..].iter().map(|s| s.to_string()).collect();
 
-                    Ok(Some(Cue::Fire { channels: channels }))
+                    Ok(vec![Cue::Fire { channels }])
                 },
 
                 // Parse a "pause" command. No arguments.
@@ -157,7 +636,34 @@ impl Cue {
                         return ScriptError::parse_err_numargs(lineno);
                     }
 
-                    Ok(Some(Cue::Pause))
+                    Ok(vec![Cue::Pause])
+                },
+
+                // Parse an "at" command. The first argument is a show time,
+                // and the rest of the line is an optional cue to run then.
+                "at" => {
+                    if args.len() < 2 {
+                        return ScriptError::parse_err_numargs(lineno);
+                    }
+
+                    let time = match parse_timecode(args[1]) {
+                        Some(time) => time,
+                        None => return ScriptError::parse_err(lineno, "Invalid show time"),
+                    };
+
+                    let rest = line.trim_start()[2..].trim_start()[args[1].len()..].trim_start();
+                    let cue = Cue::from_line(rest, lineno)?;
+                    match cue.first() {
+                        Some(&Cue::Board { .. }) | Some(&Cue::Channel { .. }) |
+                        Some(&Cue::Group { .. }) | Some(&Cue::Threshold { .. }) |
+                        Some(&Cue::Igniter { .. }) | Some(&Cue::At { .. }) =>
+                            ScriptError::parse_err(lineno, "Invalid command after at"),
+                        _ => {
+                            let mut cues = vec![Cue::At { time }];
+                            cues.extend(cue);
+                            Ok(cues)
+                        },
+                    }
                 },
 
                 // Any other command is an error.
@@ -167,175 +673,649 @@ impl Cue {
     }
 }
 
+impl Cue {
+    /// Whether the cue reads back unchanged from the script line it is
+    /// displayed as. This isn't so for cues made some other way with, for
+    /// example, a space in a name or a line break in a message.
+    pub fn is_writable(&self) -> bool {
+        let line = self.to_string();
+        if line.contains(['\n', '\r']) {
+            return false;
+        }
+        match Cue::from_line(&line, 0) {
+            Ok(cues) => cues.len() == 1 && cues[0] == *self,
+            Err(_) => false,
+        }
+    }
+}
+
+/// The text of `line` after its first word, `command`, without the spaces
+/// around it.
+fn rest_of_line(line: &str, command: &str) -> String {
+    line.trim()[command.len()..].trim().to_string()
+}
+
+/// Replace any group names in `names` with the channels in that group.
+fn expand_groups(groups: &HashMap<String, Vec<String>>, names: &[String]) -> Vec<String> {
+    let mut expanded = Vec::new();
+    for name in names {
+        match groups.get(name) {
+            Some(channels) => expanded.extend(channels.iter().cloned()),
+            None => expanded.push(name.clone()),
+        }
+    }
+    expanded
+}
+
 impl Script {
     pub fn from_file<P: AsRef<Path>>(path: P) -> ScriptResult<Script> {
-        let f = File::open(path)?;
-        let bf = BufReader::new(&f);
-        Script::from_bufreader(bf)
+        Script::from_file_all_errors(path).map_err(|mut errors| errors.remove(0))
     }
 
     pub fn from_string(script: String) -> ScriptResult<Script> {
-        let bf = BufReader::new(script.as_bytes());
-        Script::from_bufreader(bf)
+        Script::from_string_all_errors(script).map_err(|mut errors| errors.remove(0))
     }
 
-    fn from_bufreader<B: BufRead>(bf: B) -> ScriptResult<Script> {
-        let mut cues: Vec<Cue> = Vec::new();
-        let mut duration = 0;
-        let mut boards: HashMap<String, [u8; 6]> = HashMap::new();
-        let mut channels: HashMap<String, (String, u8)> = HashMap::new();
-        let mut channels_fired: Vec<String> = Vec::new();
-        let mut sleep_since_fire = true;
+    /// Like `from_file`, but carries on after an error to report every error
+    /// in the script, in the order they appear.
+    pub fn from_file_all_errors<P: AsRef<Path>>(path: P) -> Result<Script, Vec<ScriptError>> {
+        let path = path.as_ref();
+        let f = File::open(path).map_err(|e| vec![ScriptError::from(e)])?;
+        let mut parser = Parser::new();
+        if let Ok(canonical) = path.canonicalize() {
+            parser.files.push(canonical);
+        }
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        parser.parse(BufReader::new(&f), dir);
+        parser.finish()
+    }
 
-        for (lineno, line) in bf.lines().enumerate() {
-            match Cue::from_line(&line?, lineno+1)? {
-                Some(cue) => {
-
-                    match &cue {
-                        // For board cues, add the board to the script
-                        &Cue::Board { ref board_id, ref mac_address } => {
-                            // Check board name not already used
-                            if boards.contains_key(board_id) {
-                                return Err(ScriptError::DuplicateBoardId {
-                                    lineno: lineno+1, board_id: board_id.clone()
-                                });
-                            }
-
-                            // Check board MAC not already used
-                            for &mac in boards.values() {
-                                if mac == *mac_address {
-                                    return Err(ScriptError::DuplicateBoardMac {
-                                        lineno: lineno+1, mac_address: *mac_address
-                                    });
-                                }
-                            }
-
-                            boards.insert(board_id.clone(), mac_address.clone());
-                        },
+    /// Build a script from cues which have already been parsed, each with the
+    /// line to report errors against, checking them just as if they had been
+    /// read from a file.
+    pub fn from_cues_all_errors(cues: Vec<(SourceLine, Cue)>)
+        -> Result<Script, Vec<ScriptError>>
+    {
+        let mut parser = Parser::new();
+        for (line, cue) in cues {
+            let first_error = parser.errors.len();
+            parser.path = line.path;
+            parser.add(cue, line.lineno);
+            if let Some(path) = parser.path.take() {
+                parser.wrap_errors(&path, first_error);
+            }
+        }
+        parser.finish()
+    }
 
-                        // For channel cues, add the channel to the script
-                        &Cue::Channel { ref name, ref board_id, ref num } => {
-                            // Check channel name not already used
-                            if channels.contains_key(name) {
-                                return Err(ScriptError::DuplicateChannel {
-                                    lineno: lineno+1, name: name.clone()
-                                });
-                            }
-
-                            // Check board+num not already used
-                            for &(ref ch_board, ref ch_num) in channels.values() {
-                                if *board_id == *ch_board && *num == *ch_num {
-                                    return Err(ScriptError::DuplicateChannel {
-                                        lineno: lineno+1, name: name.clone()
-                                    });
-                                }
-                            }
-
-                            // Check board exists
-                            if !boards.contains_key(board_id) {
-                                return Err(ScriptError::UnknownBoardId {
-                                    lineno: lineno+1,
-                                    board_id: board_id.clone()
-                                });
-                            }
-
-                            // Check num is 1..30
-                            if *num == 0 || *num > 30 {
-                                return Err(ScriptError::InvalidChannelNum {
-                                    lineno: lineno+1,
-                                    num: *num
-                                });
-                            }
-
-                            channels.insert(name.clone(), (board_id.clone(), *num));
-                        },
+    /// Like `from_string`, but carries on after an error to report every
+    /// error in the script, in the order they appear. Included files are
+    /// found relative to the current directory.
+    pub fn from_string_all_errors(script: String) -> Result<Script, Vec<ScriptError>> {
+        Script::from_string_in_dir_all_errors(script, Path::new(""))
+    }
 
-                        // For sleep cues, accumulate total time slept,
-                        // and record that we've seen a sleep since the
-                        // last fire cue.
-                        &Cue::Sleep { time } => {
-                            duration += time;
-                            sleep_since_fire = true;
-                        },
+    /// Like `from_string_all_errors`, but with included files found relative
+    /// to `dir`, as for a script file in that directory.
+    pub fn from_string_in_dir_all_errors(script: String, dir: &Path)
+        -> Result<Script, Vec<ScriptError>>
+    {
+        let mut parser = Parser::new();
+        parser.parse(BufReader::new(script.as_bytes()), dir);
+        parser.finish()
+    }
 
-                        // For pause cues, just update the sleep_since_fire.
-                        &Cue::Pause => sleep_since_fire = true,
-
-                        // For fire cues, check all channel names are defined,
-                        // check no more than three channels fired per board,
-                        // and check there has been a sleep cue since the last
-                        // fire cue.
-                        &Cue::Fire { channels: ref cue_channels } =>
-                        {
-                            // Check we've slept since the previous Fire cue
-                            if !sleep_since_fire {
-                                return Err(ScriptError::FireWithoutSleep {
-                                    lineno: lineno+1
-                                });
-                            }
-
-                            // Store a count of how many channels have been fired on each
-                            // board, so we can enforce the 3-per-go limit.
-                            let mut board_counts: HashMap<String, usize> = HashMap::new();
-                            for board in boards.keys() {
-                                board_counts.insert(board.clone(), 0);
-                            }
-
-                            for channel in cue_channels {
-                                // Check channel hasn't already been fired
-                                if channels_fired.contains(channel) {
-                                    return Err(ScriptError::ChannelRefired {
-                                        lineno: lineno+1, channel: channel.clone()
-                                    });
-                                }
-
-                                // Check channel has been defined
-                                if !channels.contains_key(channel) {
-                                    return Err(ScriptError::UndefinedChannel {
-                                        lineno: lineno+1, channel: channel.clone()
-                                    });
-                                }
-
-                                // Check board fire count
-                                let (ref board_id, _) = channels[channel];
-                                *board_counts.entry(board_id.clone()).or_insert(0) += 1;
-                                if board_counts[board_id] > 3 {
-                                    return Err(ScriptError::ExcessChannelsPerBoard {
-                                        lineno: lineno+1, board_id: board_id.clone()
-                                    });
-                                }
-
-                                channels_fired.push(channel.clone());
-                            }
-
-                            sleep_since_fire = false;
-                        },
+    /// Expand any group names in a list of channel names, such as from a
+    /// fire cue, into the channels they contain. Other names are unchanged.
+    pub fn expand_channels(&self, names: &[String]) -> Vec<String> {
+        expand_groups(&self.groups, names)
+    }
 
-                        // Don't care about any other cue types specifically
-                        _ => {},
-                    };
+    /// Work out the fire commands to send for a fire cue. Each board takes
+    /// at most three channels per command, so the channels are split into
+    /// steps, where every board in a step is fired at once and steps are
+    /// separated by the fire gap. Boards are in order of ID in each step.
+    pub fn fire_steps(&self, names: &[String]) -> Vec<Vec<(String, [u8; 3])>> {
+        let mut board_channels: HashMap<&String, Vec<u8>> = HashMap::new();
+        for channel in self.expand_channels(names) {
+            let (ref board_id, num) = self.channels[&channel];
+            board_channels.entry(board_id).or_default().push(num);
+        }
+        let mut board_ids: Vec<&String> = board_channels.keys().cloned().collect();
+        board_ids.sort();
+
+        let mut steps: Vec<Vec<(String, [u8; 3])>> = Vec::new();
+        for board_id in board_ids {
+            for (idx, chunk) in board_channels[board_id].chunks(3).enumerate() {
+                if steps.len() <= idx {
+                    steps.push(Vec::new());
+                }
+                let mut firing_chans = [0u8; 3];
+                firing_chans[..chunk.len()].copy_from_slice(chunk);
+                steps[idx].push((board_id.clone(), firing_chans));
+            }
+        }
+        steps
+    }
+}
+
+/// Parser state, carried through any included files.
+struct Parser {
+    cues: Vec<Cue>,
+    lines: Vec<SourceLine>,
+    duration: Duration,
+    sequencing: Duration,
+    fire_gap: Duration,
+    boards: HashMap<String, [u8; 6]>,
+    channels: HashMap<String, (String, u8)>,
+    groups: HashMap<String, Vec<String>>,
+    channel_info: HashMap<String, ChannelInfo>,
+    thresholds: HashMap<ThresholdName, (f32, SourceLine)>,
+    igniters: HashMap<String, Igniter>,
+    channel_igniters: HashMap<String, String>,
+    channels_fired: Vec<String>,
+    sleep_since_fire: bool,
+    errors: Vec<ScriptError>,
+    /// Files currently being read, outermost first, to detect include cycles.
+    files: Vec<PathBuf>,
+    /// The file being read, or None for the script itself.
+    path: Option<PathBuf>,
+}
+
+impl Parser {
+    fn new() -> Parser {
+        Parser {
+            cues: Vec::new(),
+            lines: Vec::new(),
+            duration: Duration::from_secs(0),
+            sequencing: Duration::from_secs(0),
+            fire_gap: Duration::from_millis(DEFAULT_FIRE_GAP_MS),
+            boards: HashMap::new(),
+            channels: HashMap::new(),
+            groups: HashMap::new(),
+            channel_info: HashMap::new(),
+            thresholds: HashMap::new(),
+            igniters: HashMap::new(),
+            channel_igniters: HashMap::new(),
+            channels_fired: Vec::new(),
+            sleep_since_fire: true,
+            errors: Vec::new(),
+            files: Vec::new(),
+            path: None,
+        }
+    }
+
+    fn finish(self) -> Result<Script, Vec<ScriptError>> {
+        if self.errors.is_empty() {
+            Ok(Script {
+                cues: self.cues, lines: self.lines, boards: self.boards, channels: self.channels,
+                groups: self.groups, channel_info: self.channel_info,
+                thresholds: self.thresholds, igniters: self.igniters,
+                channel_igniters: self.channel_igniters, duration: self.duration, sequencing: self.sequencing
+            })
+        } else {
+            Err(self.errors)
+        }
+    }
+
+    /// Parse every line from `bf`. Relative include and patch paths are found
+    /// in `dir`.
+    fn parse<B: BufRead>(&mut self, bf: B, dir: &Path) {
+        for (lineno, line) in bf.lines().enumerate() {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => {
+                    self.errors.push(ScriptError::from(e));
+                    break;
+                },
+            };
+
+            // Commands which read other files are handled here rather than
+            // becoming cues, with the rest of the line being a file path.
+            let trimmed = line.trim();
+            let command = trimmed.split_whitespace().next().unwrap_or("");
+            if command == "include" || command == "patch" || command == "labels" {
+                let path = trimmed[command.len()..].trim();
+                if path.is_empty() {
+                    self.errors.push(ScriptError::Parse {
+                        lineno: lineno+1, error: "Wrong number of arguments"
+                    });
+                } else if command == "include" {
+                    self.include(&dir.join(path), lineno+1);
+                } else if command == "patch" {
+                    self.patch(&dir.join(path), lineno+1);
+                } else {
+                    self.labels(&dir.join(path), lineno+1);
+                }
+                continue;
+            }
 
-                    cues.push(cue);
+            let line_cues = match Cue::from_line(&line, lineno+1) {
+                Ok(line_cues) => line_cues,
+                Err(e) => {
+                    self.errors.push(e);
+                    continue;
                 },
-                None => {},
+            };
+
+            // Cues with an error are reported and then left out. A fire cue
+            // with an error still counts its other channels as fired, so
+            // they aren't reported again if fired later in the script.
+            for cue in line_cues {
+                self.add(cue, lineno+1);
+            }
+        }
+    }
+
+    /// Check a cue and add it to the script, or record its errors and return
+    /// false.
+    fn add(&mut self, cue: Cue, lineno: usize) -> bool {
+        let errors = self.errors.len();
+        match self.check(&cue, lineno) {
+            Ok(()) if self.errors.len() == errors => {
+                self.cues.push(cue);
+                let line = self.line(lineno);
+                self.lines.push(line);
+                true
+            },
+            Ok(()) => false,
+            Err(e) => {
+                self.errors.push(e);
+                false
+            },
+        }
+    }
+
+    /// Line `lineno` of the file being read.
+    fn line(&self, lineno: usize) -> SourceLine {
+        SourceLine { path: self.path.clone(), lineno }
+    }
+
+    /// Add the boards and channels described by the patch file at `path`.
+    fn patch(&mut self, path: &Path, lineno: usize) {
+        let f = match File::open(path) {
+            Ok(f) => f,
+            Err(error) => {
+                self.errors.push(ScriptError::IncludeFailed {
+                    lineno, path: path.to_path_buf(), error
+                });
+                return;
+            },
+        };
+
+        let first_error = self.errors.len();
+        let outer = self.path.replace(path.to_path_buf());
+        match patch::read_patch(BufReader::new(f)) {
+            Ok(rows) => self.patch_rows(rows),
+            Err(errors) => self.errors.extend(errors),
+        }
+        self.path = outer;
+        self.wrap_errors(path, first_error);
+    }
+
+    /// Add fire cues at the times of the labels in the Audacity label track
+    /// export at `path`.
+    fn labels(&mut self, path: &Path, lineno: usize) {
+        let f = match File::open(path) {
+            Ok(f) => f,
+            Err(error) => {
+                self.errors.push(ScriptError::IncludeFailed {
+                    lineno, path: path.to_path_buf(), error
+                });
+                return;
+            },
+        };
+
+        let first_error = self.errors.len();
+        let outer = self.path.replace(path.to_path_buf());
+        match audacity::read_labels(BufReader::new(f)) {
+            Ok(cues) => for (lineno, cue) in cues {
+                self.add(cue, lineno);
+            },
+            Err(errors) => self.errors.extend(errors),
+        }
+        self.path = outer;
+        self.wrap_errors(path, first_error);
+    }
+
+    /// Wrap the errors from `first_error` onwards to say they came from the
+    /// file at `path`.
+    fn wrap_errors(&mut self, path: &Path, first_error: usize) {
+        let errors: Vec<ScriptError> = self.errors.drain(first_error..).collect();
+        for error in errors {
+            self.errors.push(ScriptError::Included {
+                path: path.to_path_buf(), error: Box::new(error)
+            });
+        }
+    }
+
+    /// Add boards and channels from patch rows, checking them just as if
+    /// they were board and channel cues.
+    fn patch_rows(&mut self, rows: Vec<PatchRow>) {
+        // A board's MAC address may be given on any of its rows, but must
+        // agree wherever it is given.
+        let mut macs: HashMap<String, [u8; 6]> = HashMap::new();
+        for row in &rows {
+            if let Some(mac) = row.mac_address {
+                match macs.get(&row.board_id) {
+                    Some(&other) if other != mac => self.errors.push(ScriptError::Parse {
+                        lineno: row.lineno, error: "MAC address differs from an earlier row"
+                    }),
+                    _ => { macs.insert(row.board_id.clone(), mac); },
+                }
+            }
+        }
+
+        // Each board is declared at its first row.
+        let mut declared: Vec<String> = Vec::new();
+        for row in rows {
+            if !declared.contains(&row.board_id) {
+                declared.push(row.board_id.clone());
+                match macs.get(&row.board_id) {
+                    Some(&mac_address) => {
+                        let cue = Cue::Board { board_id: row.board_id.clone(), mac_address };
+                        self.add(cue, row.lineno);
+                    },
+                    None => self.errors.push(ScriptError::Parse {
+                        lineno: row.lineno, error: "No MAC address given for board"
+                    }),
+                }
             }
+
+            if let Some(channel) = row.channel {
+                let cue = Cue::Channel {
+                    name: channel.name.clone(), board_id: row.board_id, num: channel.num,
+                    igniter: channel.igniter,
+                };
+                if self.add(cue, row.lineno) {
+                    self.channel_info.insert(channel.name, channel.info);
+                }
+            }
+        }
+    }
+
+    /// Parse the file at `path` as though its contents were at `lineno`.
+    fn include(&mut self, path: &Path, lineno: usize) {
+        let canonical = match path.canonicalize() {
+            Ok(canonical) => canonical,
+            Err(error) => {
+                self.errors.push(ScriptError::IncludeFailed {
+                    lineno, path: path.to_path_buf(), error
+                });
+                return;
+            },
+        };
+        if self.files.contains(&canonical) {
+            self.errors.push(ScriptError::IncludeCycle { lineno, path: path.to_path_buf() });
+            return;
         }
+        let f = match File::open(&canonical) {
+            Ok(f) => f,
+            Err(error) => {
+                self.errors.push(ScriptError::IncludeFailed {
+                    lineno, path: path.to_path_buf(), error
+                });
+                return;
+            },
+        };
+
+        // Errors in the included file are wrapped to say which file they
+        // came from.
+        let first_error = self.errors.len();
+        let dir = canonical.parent().map(|d| d.to_path_buf()).unwrap_or_default();
+        self.files.push(canonical);
+        let outer = self.path.replace(path.to_path_buf());
+        self.parse(BufReader::new(&f), &dir);
+        self.path = outer;
+        self.files.pop();
+        self.wrap_errors(path, first_error);
+    }
+
+    /// The continuity reading counted as nothing connected, as set so far.
+    fn open_threshold(&self) -> u8 {
+        match self.thresholds.get(&ThresholdName::Open) {
+            Some(&(value, _)) => value as u8,
+            None => OPEN,
+        }
+    }
+
+    /// Check a cue against the script so far and update the script state.
+    /// The cue should be left out of the script if an error is returned.
+    /// Fire cues record their errors directly, as there may be several, and
+    /// should also be left out if they record any.
+    fn check(&mut self, cue: &Cue, lineno: usize) -> ScriptResult<()> {
+        match cue {
+            // For board cues, add the board to the script
+            Cue::Board { board_id, mac_address } => {
+                // Check board name not already used
+                if self.boards.contains_key(board_id) {
+                    return Err(ScriptError::DuplicateBoardId {
+                        lineno, board_id: board_id.clone()
+                    });
+                }
+
+                // Check board MAC not already used
+                if self.boards.values().any(|mac| mac == mac_address) {
+                    return Err(ScriptError::DuplicateBoardMac {
+                        lineno, mac_address: *mac_address
+                    });
+                }
+
+                self.boards.insert(board_id.clone(), *mac_address);
+            },
+
+            // For channel cues, add the channel to the script
+            Cue::Channel { name, board_id, num, igniter } => {
+                // Check channel name not already used
+                if self.channels.contains_key(name) || self.groups.contains_key(name) {
+                    return Err(ScriptError::DuplicateChannel {
+                        lineno, name: name.clone()
+                    });
+                }
+
+                // Check board+num not already used
+                if self.channels.values().any(|(ch_board, ch_num)| {
+                    *board_id == *ch_board && *num == *ch_num
+                }) {
+                    return Err(ScriptError::DuplicateChannel {
+                        lineno, name: name.clone()
+                    });
+                }
+
+                // Check board exists
+                if !self.boards.contains_key(board_id) {
+                    return Err(ScriptError::UnknownBoardId {
+                        lineno, board_id: board_id.clone()
+                    });
+                }
+
+                // Check num is 1..30
+                if *num == 0 || *num > 30 {
+                    return Err(ScriptError::InvalidChannelNum { lineno, num: *num });
+                }
+
+                // Check the igniter type exists
+                if let Some(igniter) = igniter {
+                    if !self.igniters.contains_key(igniter) {
+                        return Err(ScriptError::UnknownIgniter {
+                            lineno, name: igniter.clone()
+                        });
+                    }
+                    self.channel_igniters.insert(name.clone(), igniter.clone());
+                }
+
+                self.channels.insert(name.clone(), (board_id.clone(), *num));
+            },
+
+            // For group cues, add the group to the script
+            Cue::Group { name, channels: group_channels } => {
+                // Check group name not already used
+                if self.channels.contains_key(name) || self.groups.contains_key(name) {
+                    return Err(ScriptError::DuplicateGroup {
+                        lineno, name: name.clone()
+                    });
+                }
+
+                // Check all channels in the group are defined
+                let expanded = expand_groups(&self.groups, group_channels);
+                let mut undefined = expanded.iter()
+                                            .filter(|c| !self.channels.contains_key(*c))
+                                            .map(|c| ScriptError::UndefinedChannel {
+                                                lineno, channel: c.clone()
+                                            })
+                                            .collect::<Vec<_>>();
+                if let Some(last) = undefined.pop() {
+                    self.errors.extend(undefined);
+                    return Err(last);
+                }
+
+                self.groups.insert(name.clone(), expanded);
+            },
+
+            // For igniter cues, check the range is the right way round and
+            // is below the open threshold, and the name isn't taken
+            &Cue::Igniter { ref name, min, max } => {
+                if min > max || max >= self.open_threshold() {
+                    return ScriptError::parse_err(lineno, "Invalid resistance range");
+                }
+                if self.igniters.contains_key(name) {
+                    return Err(ScriptError::DuplicateIgniter { lineno, name: name.clone() });
+                }
+                let line = self.line(lineno);
+                self.igniters.insert(name.clone(), Igniter { min, max, line });
+            },
+
+            // For threshold cues, check the value makes sense and the
+            // threshold hasn't already been set. An open threshold must be
+            // above the range of every igniter already defined.
+            &Cue::Threshold { name, value } => {
+                if !name.is_valid(value) {
+                    return ScriptError::parse_err(lineno, "Invalid threshold value");
+                }
+                if name == ThresholdName::Open
+                   && self.igniters.values().any(|i| i.max as f32 >= value)
+                {
+                    return ScriptError::parse_err(lineno, "Open threshold within igniter range");
+                }
+                if self.thresholds.contains_key(&name) {
+                    return Err(ScriptError::DuplicateThreshold { lineno, name });
+                }
+                let line = self.line(lineno);
+                self.thresholds.insert(name, (value, line));
+            },
+
+            // For sleep cues, accumulate total time slept,
+            // and record that we've seen a sleep since the
+            // last fire cue.
+            &Cue::Sleep { time } => {
+                self.duration += time;
+                self.sleep_since_fire = true;
+            },
+
+            // For at cues, check show time isn't going backwards,
+            // then treat the time until then as a sleep.
+            &Cue::At { time } => {
+                if time < self.duration {
+                    return Err(ScriptError::NonMonotonicTime { lineno, time });
+                }
+                if time > self.duration {
+                    self.sleep_since_fire = true;
+                }
+                self.duration = time;
+            },
+
+            // For pause cues, just update the sleep_since_fire.
+            &Cue::Pause => self.sleep_since_fire = true,
+
+            // For fire gap cues, use the new gap from now on.
+            &Cue::FireGap { time } => self.fire_gap = time,
+
+            // For fire cues, expand any groups, then check all
+            // channel names are defined and check there has been a
+            // sleep cue since the last fire cue. Add the time taken
+            // to sequence more than three channels on a board.
+            Cue::Fire { channels: cue_channels } => {
+                // Check we've slept since the previous Fire cue
+                if !self.sleep_since_fire {
+                    self.errors.push(ScriptError::FireWithoutSleep { lineno });
+                }
+
+                // Store a count of how many channels have been fired on each
+                // board, to work out how many fire commands are needed.
+                let mut board_counts: HashMap<String, u32> = HashMap::new();
+
+                for channel in &expand_groups(&self.groups, cue_channels) {
+                    // Check channel hasn't already been fired
+                    if self.channels_fired.contains(channel) {
+                        self.errors.push(ScriptError::ChannelRefired {
+                            lineno, channel: channel.clone()
+                        });
+                        continue;
+                    }
+
+                    // Check channel has been defined
+                    if !self.channels.contains_key(channel) {
+                        self.errors.push(ScriptError::UndefinedChannel {
+                            lineno, channel: channel.clone()
+                        });
+                        continue;
+                    }
+
+                    let (ref board_id, _) = self.channels[channel];
+                    *board_counts.entry(board_id.clone()).or_insert(0) += 1;
 
-        Ok(Script { cues: cues, boards: boards, duration: duration, channels: channels })
+                    self.channels_fired.push(channel.clone());
+                }
+
+                let steps = board_counts.values().map(|n| n.div_ceil(3)).max();
+                if let Some(steps) = steps {
+                    let time = self.fire_gap * (steps - 1);
+                    self.duration += time;
+                    self.sequencing += time;
+                }
+
+                self.sleep_since_fire = false;
+            },
+
+            // Don't care about any other cue types specifically
+            _ => {},
+        }
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Script, Cue};
+    use super::{Igniter, Parser, Script, ScriptError, SourceLine, Cue, format_timecode};
+    use checks::ThresholdName;
+    use format::{format_script, format_source};
     use std::collections::HashMap;
+    use std::time::Duration;
+    use std::io::BufReader;
+    use std::path::{Path, PathBuf};
+    use std::{env, fs};
+
+    /// Write each (name, contents) pair into a fresh temporary directory.
+    fn write_files(dir: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = env::temp_dir().join(format!("iris-test-{}", dir));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for &(name, contents) in files {
+            fs::write(dir.join(name), contents).unwrap();
+        }
+        dir
+    }
 
     #[test]
     fn empty_script() {
         let script_string = "".to_string();
         let script = Script::from_string(script_string).unwrap();
         assert_eq!(script,
-            Script { cues: vec![], boards: HashMap::new(), channels: HashMap::new(), duration: 0 })
+            Script { cues: vec![], lines: vec![], boards: HashMap::new(), channels: HashMap::new(),
+                     groups: HashMap::new(), channel_info: HashMap::new(),
+                     thresholds: HashMap::new(), igniters: HashMap::new(),
+                     channel_igniters: HashMap::new(), duration: Duration::from_secs(0),
+                     sequencing: Duration::from_secs(0) })
     }
 
     #[test]
@@ -362,10 +1342,12 @@ mod tests {
         sleep 5
         ".to_string();
 
-        assert_eq!(
-            Script::from_string(script_string_1).unwrap(),
-            Script::from_string(script_string_2).unwrap()
-        );
+        // Only the line numbers of the cues differ
+        let script_1 = Script::from_string(script_string_1).unwrap();
+        let script_2 = Script::from_string(script_string_2).unwrap();
+        assert_eq!(script_1.cues, script_2.cues);
+        assert_eq!(script_1.duration, script_2.duration);
+        assert_eq!(script_2.lines, vec![SourceLine::script(3), SourceLine::script(5)]);
     }
 
     #[test]
@@ -378,10 +1360,31 @@ mod tests {
 
         assert_eq!(
             Script::from_string(script_string).unwrap().duration,
-            10
+            Duration::from_secs(10)
         );
     }
 
+    #[test]
+    fn subsecond_duration() {
+        let script_string = "
+        sleep 0.25
+        sleep 250ms
+        sleep 1.5s
+        sleep .3
+        sleep 2
+        ".to_string();
+
+        let script = Script::from_string(script_string).unwrap();
+        assert_eq!(script.cues, vec![
+            Cue::Sleep { time: Duration::from_millis(250) },
+            Cue::Sleep { time: Duration::from_millis(250) },
+            Cue::Sleep { time: Duration::from_millis(1500) },
+            Cue::Sleep { time: Duration::from_millis(300) },
+            Cue::Sleep { time: Duration::from_secs(2) },
+        ]);
+        assert_eq!(script.duration, Duration::from_millis(4300));
+    }
+
     #[test]
     #[should_panic(expected="Invalid sleep time")]
     fn invalid_sleep_time() {
@@ -391,6 +1394,92 @@ mod tests {
         Script::from_string(script_string).unwrap();
     }
 
+    #[test]
+    #[should_panic(expected="Invalid sleep time")]
+    fn invalid_sleep_time_2() {
+        let script_string = "
+        sleep 1.5.2ms
+        ".to_string();
+        Script::from_string(script_string).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected="Invalid sleep time")]
+    fn invalid_sleep_time_3() {
+        let script_string = "
+        sleep -1
+        ".to_string();
+        Script::from_string(script_string).unwrap();
+    }
+
+    #[test]
+    fn at_times() {
+        let script_string = "
+        board 001 00:00:00:00:00:00
+        channel ch1 001 1
+        channel ch2 001 2
+        channel ch3 001 3
+        at 00:01.500 fire ch1
+        sleep 1
+        at 1:02.25 fire   ch2
+        at 1:00:00
+        fire ch3
+        ".to_string();
+
+        let script = Script::from_string(script_string).unwrap();
+        assert_eq!(&script.cues[4..], &[
+            Cue::At { time: Duration::from_millis(1500) },
+            Cue::Fire { channels: vec!["ch1".to_string()] },
+            Cue::Sleep { time: Duration::from_secs(1) },
+            Cue::At { time: Duration::from_millis(62250) },
+            Cue::Fire { channels: vec!["ch2".to_string()] },
+            Cue::At { time: Duration::from_secs(3600) },
+            Cue::Fire { channels: vec!["ch3".to_string()] },
+        ]);
+        assert_eq!(script.duration, Duration::from_secs(3600));
+    }
+
+    #[test]
+    #[should_panic(expected="NonMonotonicTime")]
+    fn at_time_backwards() {
+        let script_string = "
+        sleep 5
+        at 00:04 print hello
+        ".to_string();
+        Script::from_string(script_string).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected="FireWithoutSleep")]
+    fn at_same_time_fire() {
+        let script_string = "
+        board 001 00:00:00:00:00:00
+        channel ch1 001 1
+        channel ch2 001 2
+        at 00:01 fire ch1
+        at 00:01 fire ch2
+        ".to_string();
+        Script::from_string(script_string).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected="Invalid show time")]
+    fn invalid_at_time() {
+        let script_string = "
+        at 01:60 print hello
+        ".to_string();
+        Script::from_string(script_string).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected="Invalid command after at")]
+    fn invalid_at_command() {
+        let script_string = "
+        at 00:01 board 001 00:00:00:00:00:00
+        ".to_string();
+        Script::from_string(script_string).unwrap();
+    }
+
     #[test]
     fn boards() {
         let script_string = "
@@ -480,6 +1569,85 @@ mod tests {
         Script::from_string(script_string).unwrap();
     }
 
+    #[test]
+    fn groups() {
+        let script_string = "
+        board 001 00:00:00:00:00:00
+        board 002 00:00:00:00:00:01
+        channel ch1 001 1
+        channel ch2 001 2
+        channel ch3 002 1
+        group left ch1 ch2
+        group all left ch3
+        fire all
+        ".to_string();
+
+        let script = Script::from_string(script_string).unwrap();
+        let mut groups = HashMap::new();
+        groups.insert("left".to_string(), vec!["ch1".to_string(), "ch2".to_string()]);
+        groups.insert("all".to_string(), vec!["ch1".to_string(), "ch2".to_string(),
+                                              "ch3".to_string()]);
+        assert_eq!(script.groups, groups);
+        assert_eq!(script.cues.last(), Some(&Cue::Fire { channels: vec!["all".to_string()] }));
+        assert_eq!(script.expand_channels(&["all".to_string()]),
+                   vec!["ch1".to_string(), "ch2".to_string(), "ch3".to_string()]);
+    }
+
+    #[test]
+    #[should_panic(expected="DuplicateGroup")]
+    fn duplicate_group() {
+        let script_string = "
+        board 001 00:00:00:00:00:00
+        channel ch1 001 1
+        group ch1 ch1
+        ".to_string();
+        Script::from_string(script_string).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected="UndefinedChannel")]
+    fn group_undefined_channel() {
+        let script_string = "
+        board 001 00:00:00:00:00:00
+        channel ch1 001 1
+        group g ch1 ch2
+        ".to_string();
+        Script::from_string(script_string).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected="ChannelRefired")]
+    fn group_refired() {
+        let script_string = "
+        board 001 00:00:00:00:00:00
+        channel ch1 001 1
+        channel ch2 001 2
+        group g ch1 ch2
+        fire ch2
+        sleep 1
+        fire g
+        ".to_string();
+        Script::from_string(script_string).unwrap();
+    }
+
+    #[test]
+    fn group_fire_steps() {
+        let script_string = "
+        board 001 00:00:00:00:00:00
+        channel ch1 001 1
+        channel ch2 001 2
+        channel ch3 001 3
+        channel ch4 001 4
+        group g ch1 ch2 ch3
+        fire g ch4
+        ".to_string();
+        let script = Script::from_string(script_string).unwrap();
+        assert_eq!(script.fire_steps(&["g".to_string(), "ch4".to_string()]), vec![
+            vec![("001".to_string(), [1, 2, 3])],
+            vec![("001".to_string(), [4, 0, 0])],
+        ]);
+    }
+
     #[test]
     #[should_panic(expected="UnknownBoardId")]
     fn unknown_channel_board() {
@@ -547,19 +1715,386 @@ mod tests {
     }
 
     #[test]
-    #[should_panic(expected="ExcessChannelsPerBoard")]
     fn excess_channels_per_board() {
         let script_string = "
         board 001 00:00:00:00:00:00
+        board 002 00:00:00:00:00:01
         channel ch1 001 1
         channel ch2 001 2
         channel ch3 001 3
         channel ch4 001 4
-        fire ch1 ch2 ch3 ch4
+        channel ch5 001 5
+        channel ch6 001 6
+        channel ch7 001 7
+        channel chA 002 1
+        fire ch1 ch2 ch3 ch4 chA
+        firegap 250ms
+        sleep 1
+        fire ch5 ch6 ch7
+        sleep 1
+        ".to_string();
+
+        let script = Script::from_string(script_string).unwrap();
+        assert_eq!(script.sequencing, Duration::from_millis(100));
+        assert_eq!(script.duration, Duration::from_millis(2100));
+
+        let names: Vec<String> = ["ch1", "ch2", "ch3", "ch4", "chA", "ch5", "ch6", "ch7"]
+                                    .iter().map(|s| s.to_string()).collect();
+        assert_eq!(script.fire_steps(&names), vec![
+            vec![("001".to_string(), [1, 2, 3]), ("002".to_string(), [1, 0, 0])],
+            vec![("001".to_string(), [4, 5, 6])],
+            vec![("001".to_string(), [7, 0, 0])],
+        ]);
+    }
+
+    #[test]
+    #[should_panic(expected="Invalid fire gap time")]
+    fn invalid_fire_gap() {
+        let script_string = "
+        firegap soon
         ".to_string();
         Script::from_string(script_string).unwrap();
     }
 
+    #[test]
+    fn all_errors() {
+        let script_string = "
+        board 001 00:00:00:00:00:00
+        board 001 00:00:00:00:00:01
+        channel ch1 001 1
+        channel ch2 002 2
+        fire ch1 ch2
+        fire ch1
+        bogus
+        sleep 1
+        at 00:00.5 print oops
+        ".to_string();
+
+        let errors: Vec<String> = Script::from_string_all_errors(script_string).unwrap_err()
+                                        .iter().map(|e| format!("{:?}", e)).collect();
+        assert_eq!(errors, vec![
+            "DuplicateBoardId { lineno: 3, board_id: \"001\" }",
+            "UnknownBoardId { lineno: 5, board_id: \"002\" }",
+            "UndefinedChannel { lineno: 6, channel: \"ch2\" }",
+            "FireWithoutSleep { lineno: 7 }",
+            "ChannelRefired { lineno: 7, channel: \"ch1\" }",
+            "Parse { lineno: 8, error: \"Invalid command\" }",
+            "NonMonotonicTime { lineno: 10, time: 500ms }",
+        ]);
+    }
+
+    #[test]
+    fn indented_messages() {
+        let text = "  say Hello there\n\tprint  Ready  now \n    at 1 say  Go\n";
+        let script = Script::from_string(text.to_string()).unwrap();
+        let cues = vec![
+            Cue::Say { message: "Hello there".to_string() },
+            Cue::Print { message: "Ready  now".to_string() },
+            Cue::At { time: Duration::from_secs(1) },
+            Cue::Say { message: "Go".to_string() },
+        ];
+        assert_eq!(script.cues, cues);
+
+        for formatted in &[format_script(&script), format_source(text)] {
+            assert_eq!(Script::from_string(formatted.clone()).unwrap().cues, cues);
+        }
+    }
+
+    #[test]
+    fn errors_leave_cues_out() {
+        let mut parser = Parser::new();
+        parser.parse(BufReader::new("
+        board 001 00:00:00:00:00:00
+        channel ch1 001 1
+        channel ch2 001 1x
+        channel ch3 002 3
+        fire ch1 ch3
+        sleep 1
+        fire ch1
+        ".as_bytes()), Path::new(""));
+
+        let errors: Vec<String> = parser.errors.iter().map(|e| format!("{:?}", e)).collect();
+        assert_eq!(errors, vec![
+            "Parse { lineno: 4, error: \"Invalid firing channel\" }",
+            "UnknownBoardId { lineno: 5, board_id: \"002\" }",
+            "UndefinedChannel { lineno: 6, channel: \"ch3\" }",
+            "ChannelRefired { lineno: 8, channel: \"ch1\" }",
+        ]);
+        assert_eq!(parser.lines, vec![SourceLine::script(2), SourceLine::script(3),
+                                      SourceLine::script(7)]);
+        assert!(parser.channels_fired.contains(&"ch1".to_string()));
+    }
+
+    #[test]
+    fn display_errors() {
+        let script_string = "board 001 00:00:00:00:00:00
+channel ch1 001 1
+\tfire  ch1 ch7
+sleep 1
+at 00:00.5 print
+".to_string();
+
+        let errors = Script::from_string_all_errors(script_string.clone()).unwrap_err();
+        assert_eq!(errors[0].to_string(), "line 3: channel 'ch7' is not defined");
+        assert_eq!(errors[0].describe(&script_string), concat!(
+                   "line 3: channel 'ch7' is not defined\n",
+                   "    \tfire  ch1 ch7\n",
+                   "    \t          ^^^"));
+        assert_eq!(errors[1].describe(&script_string), concat!(
+                   "line 5: show time 00:00.500 is earlier than the show time already reached\n",
+                   "    at 00:00.5 print\n",
+                   "    ^^"));
+    }
+
+    #[test]
+    fn timecodes() {
+        assert_eq!(format_timecode(Duration::from_millis(83500)), "01:23.500");
+        assert_eq!(format_timecode(Duration::from_millis(3723004)), "01:02:03.004");
+        assert_eq!(super::parse_timecode("01:23.500"), Some(Duration::from_millis(83500)));
+        assert_eq!(super::parse_timecode("01:02:03.004"), Some(Duration::from_millis(3723004)));
+    }
+
+    #[test]
+    fn include() {
+        let dir = write_files("include", &[
+            ("patch.fire", "board 001 00:00:00:00:00:00\nchannel ch1 001 1\n"),
+            ("show.fire", "include patch.fire\nsleep 1\nfire ch1\n"),
+        ]);
+
+        let script = Script::from_file(dir.join("show.fire")).unwrap();
+        assert_eq!(script.channels["ch1"], ("001".to_string(), 1));
+        assert_eq!(script.cues.len(), 4);
+        let patch = Some(dir.join("patch.fire"));
+        assert_eq!(script.lines, vec![SourceLine { path: patch.clone(), lineno: 1 },
+                                      SourceLine { path: patch, lineno: 2 },
+                                      SourceLine::script(2), SourceLine::script(3)]);
+    }
+
+    #[test]
+    fn include_errors() {
+        let dir = write_files("include-errors", &[
+            ("patch.fire", "board 001 00:00:00:00:00:00\nchannel ch1 002 1\n"),
+            ("show.fire", "# show\ninclude patch.fire\nfire ch1\ninclude missing.fire\n"),
+        ]);
+
+        let errors = Script::from_file_all_errors(dir.join("show.fire")).unwrap_err();
+        assert_eq!(errors.len(), 3);
+        match errors[0] {
+            ScriptError::Included { ref path, ref error } => {
+                assert!(path.ends_with("patch.fire"));
+                assert_eq!(error.lineno(), Some(2));
+            },
+            ref e => panic!("expected Included, got {:?}", e),
+        }
+        assert!(errors[0].describe("").ends_with(concat!(
+            "patch.fire: line 2: board '002' is not defined\n",
+            "    channel ch1 002 1\n",
+            "                ^^^")));
+        assert_eq!(errors[1].lineno(), Some(3));
+        match errors[2] {
+            ScriptError::IncludeFailed { lineno: 4, .. } => {},
+            ref e => panic!("expected IncludeFailed, got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn include_cycle() {
+        let dir = write_files("include-cycle", &[
+            ("a.fire", "include b.fire\n"),
+            ("b.fire", "print b\ninclude a.fire\n"),
+        ]);
+
+        let errors = Script::from_file_all_errors(dir.join("a.fire")).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        match errors[0] {
+            ScriptError::Included { ref error, .. } => match **error {
+                ScriptError::IncludeCycle { lineno: 2, .. } => {},
+                ref e => panic!("expected IncludeCycle, got {:?}", e),
+            },
+            ref e => panic!("expected Included, got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn patch() {
+        let dir = write_files("patch", &[
+            ("rig.csv", concat!("board,mac,channel,number,position,effect\n",
+                                "001,00:00:00:00:00:01,ch1,1,Left,Mine\n",
+                                "001,,ch2,2,Right,Cake\n",
+                                "002,00:00:00:00:00:02,,,,\n")),
+            ("show.fire", "patch rig.csv\nsleep 1\nfire ch1 ch2\n"),
+        ]);
+
+        let script = Script::from_file(dir.join("show.fire")).unwrap();
+        assert_eq!(script.boards.len(), 2);
+        assert_eq!(script.channels["ch2"], ("001".to_string(), 2));
+        assert_eq!(script.channel_info["ch1"].position, "Left");
+        assert_eq!(script.channel_info["ch2"].effect, "Cake");
+    }
+
+    #[test]
+    fn patch_errors() {
+        let dir = write_files("patch-errors", &[
+            ("rig.csv", concat!("board,mac,channel,number\n",
+                                "001,00:00:00:00:00:01,ch1,1\n",
+                                "001,00:00:00:00:00:02,ch2,2\n",
+                                "002,,ch3,1\n",
+                                "001,,ch1,3\n")),
+            ("show.fire", "patch rig.csv\npatch missing.csv\n"),
+        ]);
+
+        let errors = Script::from_file_all_errors(dir.join("show.fire")).unwrap_err();
+        assert_eq!(errors.len(), 5);
+        let linenos: Vec<Option<usize>> = errors[..4].iter().map(|e| match *e {
+            ScriptError::Included { ref path, ref error } => {
+                assert!(path.ends_with("rig.csv"));
+                error.lineno()
+            },
+            ref e => panic!("expected Included, got {:?}", e),
+        }).collect();
+        assert_eq!(linenos, vec![Some(3), Some(4), Some(4), Some(5)]);
+        assert!(errors[1].to_string().ends_with("line 4: No MAC address given for board"));
+        match errors[4] {
+            ScriptError::IncludeFailed { lineno: 2, .. } => {},
+            ref e => panic!("expected IncludeFailed, got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn labels() {
+        let dir = write_files("labels", &[
+            ("music.txt", "2.5\t2.5\tch1\n10.000000\t12.000000\tboth\n"),
+            ("show.fire", concat!("board 001 00:00:00:00:00:01\nchannel ch1 001 1\n",
+                                  "channel ch2 001 2\nchannel ch3 001 3\n",
+                                  "group both ch2 ch3\nlabels music.txt\n")),
+        ]);
+
+        let script = Script::from_file(dir.join("show.fire")).unwrap();
+        assert_eq!(script.duration, Duration::from_secs(10));
+        assert_eq!(&script.cues[5..], &[
+            Cue::At { time: Duration::from_millis(2500) },
+            Cue::Fire { channels: vec!["ch1".to_string()] },
+            Cue::At { time: Duration::from_secs(10) },
+            Cue::Fire { channels: vec!["both".to_string()] },
+        ]);
+    }
+
+    #[test]
+    fn label_errors() {
+        let dir = write_files("label-errors", &[
+            ("music.txt", "2.5\t2.5\tch1\n2.5\t2.5\tch2\n3\t3\tch1 ch3\n"),
+            ("show.fire", "board 001 00:00:00:00:00:01\nchannel ch1 001 1\nchannel ch2 001 2\n\
+                           labels music.txt\n"),
+        ]);
+
+        let errors: Vec<String> = Script::from_file_all_errors(dir.join("show.fire"))
+                                        .unwrap_err().iter().map(|e| e.to_string()).collect();
+        let path = dir.join("music.txt").display().to_string();
+        assert_eq!(errors, vec![
+            format!("{}: line 2: no sleep or pause since the previous fire", path),
+            format!("{}: line 3: channel 'ch1' has already been fired", path),
+            format!("{}: line 3: channel 'ch3' is not defined", path),
+        ]);
+    }
+
+    #[test]
+    fn thresholds() {
+        let script = Script::from_string("
+threshold armed 20
+threshold open 200
+threshold disarmed 0.5
+".to_string()).unwrap();
+        assert_eq!(script.thresholds[&ThresholdName::Armed], (20.0, SourceLine::script(2)));
+        assert_eq!(script.thresholds[&ThresholdName::Open], (200.0, SourceLine::script(3)));
+        assert_eq!(script.thresholds[&ThresholdName::Disarmed], (0.5, SourceLine::script(4)));
+        assert_eq!(script.cues[2].to_string(), "threshold disarmed 0.5");
+    }
+
+    #[test]
+    fn threshold_errors() {
+        let errors = Script::from_string_all_errors("
+threshold armed
+threshold volts 3
+threshold armed high
+threshold open 0
+threshold open 2.5
+threshold continuity -1
+threshold armed 20
+threshold armed 12
+at 1 threshold armed 12
+".to_string()).unwrap_err();
+        let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(errors, [
+            "line 2: Wrong number of arguments",
+            "line 3: Invalid threshold name",
+            "line 4: Invalid threshold value",
+            "line 5: Invalid threshold value",
+            "line 6: Invalid threshold value",
+            "line 7: Invalid threshold value",
+            "line 9: threshold 'armed' is already set",
+            "line 10: Invalid command after at",
+        ]);
+    }
+
+    #[test]
+    fn igniters() {
+        let script = Script::from_string("
+igniter ematch 1 5
+board 001 00:00:00:00:00:01
+channel ch1 001 1 ematch
+channel ch2 001 2
+".to_string()).unwrap();
+        assert_eq!(script.igniters["ematch"], Igniter { min: 1, max: 5, line: SourceLine::script(2) });
+        assert_eq!(script.channel_igniters["ch1"], "ematch");
+        assert!(!script.channel_igniters.contains_key("ch2"));
+        assert_eq!(script.cues[2].to_string(), "channel ch1 001 1 ematch");
+    }
+
+    #[test]
+    fn igniter_errors() {
+        let errors = Script::from_string_all_errors("
+igniter ematch 1
+igniter ematch 1 five
+igniter ematch 5 1
+igniter ematch 1 255
+igniter ematch 1 5
+igniter ematch 2 6
+board 001 00:00:00:00:00:01
+channel ch1 001 1 talon
+channel ch2 001 2 ematch extra
+".to_string()).unwrap_err();
+        let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(errors, [
+            "line 2: Wrong number of arguments",
+            "line 3: Invalid resistance",
+            "line 4: Invalid resistance range",
+            "line 5: Invalid resistance range",
+            "line 7: igniter 'ematch' is already defined",
+            "line 9: igniter 'talon' is not defined",
+            "line 10: Wrong number of arguments",
+        ]);
+    }
+
+    #[test]
+    fn igniter_open_threshold() {
+        let errors = Script::from_string_all_errors("
+threshold open 100
+igniter big 150 200
+igniter edge 1 100
+igniter small 1 99
+".to_string()).unwrap_err();
+        let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(errors, ["line 3: Invalid resistance range",
+                            "line 4: Invalid resistance range"]);
+
+        let errors = Script::from_string_all_errors("
+igniter big 150 200
+threshold open 100
+".to_string()).unwrap_err();
+        let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(errors, ["line 3: Open threshold within igniter range"]);
+    }
+
     #[test]
     fn complete_script() {
         let script_string = "
@@ -590,23 +2125,23 @@ mod tests {
         let cues = vec![
             Cue::Board { board_id: "001".to_string(), mac_address: [0, 0, 0, 0, 0, 1] },
             Cue::Board { board_id: "002".to_string(), mac_address: [0, 0, 0, 0, 0, 2] },
-            Cue::Channel { name: "ch1".to_string(), board_id: "001".to_string(), num: 1 },
-            Cue::Channel { name: "ch2".to_string(), board_id: "001".to_string(), num: 2 },
-            Cue::Channel { name: "ch3".to_string(), board_id: "001".to_string(), num: 3 },
-            Cue::Channel { name: "ch4".to_string(), board_id: "001".to_string(), num: 4 },
-            Cue::Channel { name: "ch5".to_string(), board_id: "001".to_string(), num: 5 },
-            Cue::Channel { name: "chA".to_string(), board_id: "002".to_string(), num: 1 },
-            Cue::Channel { name: "chB".to_string(), board_id: "002".to_string(), num: 2 },
-            Cue::Channel { name: "chC".to_string(), board_id: "002".to_string(), num: 3 },
+            Cue::Channel { name: "ch1".to_string(), board_id: "001".to_string(), num: 1, igniter: None },
+            Cue::Channel { name: "ch2".to_string(), board_id: "001".to_string(), num: 2, igniter: None },
+            Cue::Channel { name: "ch3".to_string(), board_id: "001".to_string(), num: 3, igniter: None },
+            Cue::Channel { name: "ch4".to_string(), board_id: "001".to_string(), num: 4, igniter: None },
+            Cue::Channel { name: "ch5".to_string(), board_id: "001".to_string(), num: 5, igniter: None },
+            Cue::Channel { name: "chA".to_string(), board_id: "002".to_string(), num: 1, igniter: None },
+            Cue::Channel { name: "chB".to_string(), board_id: "002".to_string(), num: 2, igniter: None },
+            Cue::Channel { name: "chC".to_string(), board_id: "002".to_string(), num: 3, igniter: None },
             Cue::Print { message: "".to_string() },
             Cue::Print { message: "Hello".to_string() },
             Cue::Say { message: "Hello".to_string() },
             Cue::Say { message: "".to_string() },
             Cue::Pause,
-            Cue::Sleep { time: 1 },
+            Cue::Sleep { time: Duration::from_secs(1) },
             Cue::Fire { channels: vec!["ch1".to_string(), "chA".to_string(),
                                        "chB".to_string(), "chC".to_string()] },
-            Cue::Sleep { time: 2 },
+            Cue::Sleep { time: Duration::from_secs(2) },
             Cue::Fire { channels: vec!["ch2".to_string(), "ch3".to_string(), "ch4".to_string() ] },
             Cue::Pause,
             Cue::Fire { channels: vec!["ch5".to_string()] }
@@ -629,6 +2164,6 @@ mod tests {
         assert_eq!(script.cues, cues);
         assert_eq!(script.boards, boards);
         assert_eq!(script.channels, channels);
-        assert_eq!(script.duration, 3);
+        assert_eq!(script.duration, Duration::from_secs(3));
     }
 }
@@ -0,0 +1,511 @@
+use std::collections::HashMap;
+use std::io;
+use std::sync::Arc;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use clock::ShowClock;
+use portfire::{self, ArmedSession, Board, Client, FireOutcome, PortfireError, PortfireResult,
+               RetryPolicy};
+use script::{Cue, Script, SourceLine, DEFAULT_FIRE_GAP_MS};
+
+/// Commands a front-end sends to a running show.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    /// Continue from a pause.
+    Continue,
+    /// Stop the show clock until resumed.
+    Hold,
+    Resume,
+    /// Stop the show and disarm every board.
+    Abort,
+}
+
+/// Something which happened while running a show, for a front-end to report.
+/// Times are show times, and lateness is how far the show clock was past
+/// the planned time, in seconds.
+#[derive(Debug)]
+pub enum ShowEvent {
+    /// A cue is starting.
+    Cue { line: SourceLine, cue: Cue, planned: Duration, lateness: f64 },
+    /// A pause cue is waiting for `Command::Continue`.
+    Paused,
+    Held,
+    Resumed,
+    /// Fire commands are about to be sent to each of these boards at once.
+    /// In a dry run nothing more is sent.
+    FireStep { planned: Duration, lateness: f64, boards: Vec<(String, [u8; 3])> },
+    /// A board's response to a fire command.
+    Fired { board_id: String, channels: [u8; 3], outcome: FireOutcome },
+    /// Time between the first and last of several boards firing at once.
+    Skew { boards: usize, skew: Duration },
+    /// The show was aborted, with the result of disarming each board.
+    Aborted { disarmed: Vec<(Board, PortfireResult<()>)> },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShowState {
+    Running,
+    Finished,
+    Aborted,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ShowOptions {
+    /// Don't send fire commands, only report them.
+    pub dry_run: bool,
+    /// Run every cue straight away rather than at its show time.
+    pub skip_sleep: bool,
+    pub retry_policy: RetryPolicy,
+}
+
+/// Match the boards in a script to those discovered on the network by MAC
+/// address, returning the matches and the IDs of any boards not found.
+pub fn match_boards(script: &Script, discovered: &[Board])
+    -> (HashMap<String, Board>, Vec<String>)
+{
+    let mut matched = HashMap::new();
+    let mut missing = Vec::new();
+    for (board_id, mac) in script.boards.iter() {
+        match discovered.iter().find(|b| b.mac == *mac) {
+            Some(board) => { matched.insert(board_id.clone(), board.clone()); },
+            None => missing.push(board_id.clone()),
+        }
+    }
+    missing.sort();
+    (matched, missing)
+}
+
+/// A script being run against a set of boards, one cue at a time.
+///
+/// Every board is disarmed when the show is aborted or dropped. Nothing is
+/// armed by the show itself, so arm the boards from `clients` once checked.
+#[derive(Debug)]
+pub struct Show {
+    script: Script,
+    clients: HashMap<String, Arc<Client>>,
+    session: ArmedSession,
+    commands: Receiver<Command>,
+    options: ShowOptions,
+    /// Started when the first cue runs.
+    clock: Option<ShowClock>,
+    next: usize,
+    /// The planned show time of the cue being run: the total of all sleeps
+    /// so far, not counting pauses.
+    show_time: Duration,
+    fire_gap: Duration,
+    /// Channels which the boards have confirmed firing, in order.
+    fired: Vec<String>,
+    latest: Option<(f64, Duration)>,
+    state: ShowState,
+}
+
+impl Show {
+    /// Prepare to run `script` on `clients`, keyed by board ID. Boards in the
+    /// script without a client fail to fire. Commands are read from
+    /// `commands` while the show runs.
+    pub fn new(script: Script, clients: HashMap<String, Arc<Client>>,
+               commands: Receiver<Command>, options: ShowOptions) -> Show {
+        let session = ArmedSession::new(clients.values().cloned().collect());
+        Show {
+            script,
+            clients,
+            session,
+            commands,
+            options,
+            clock: None,
+            next: 0,
+            show_time: Duration::from_secs(0),
+            fire_gap: Duration::from_millis(DEFAULT_FIRE_GAP_MS),
+            fired: Vec::new(),
+            latest: None,
+            state: ShowState::Running,
+        }
+    }
+
+    pub fn script(&self) -> &Script {
+        &self.script
+    }
+
+    pub fn clients(&self) -> &HashMap<String, Arc<Client>> {
+        &self.clients
+    }
+
+    pub fn state(&self) -> ShowState {
+        self.state
+    }
+
+    /// Channels fired so far, in order.
+    pub fn fired(&self) -> &[String] {
+        &self.fired
+    }
+
+    /// Channels in the script not yet fired, sorted by name.
+    pub fn unfired(&self) -> Vec<String> {
+        let mut unfired: Vec<String> = self.script.channels.keys()
+                                           .filter(|c| !self.fired.contains(c))
+                                           .cloned()
+                                           .collect();
+        unfired.sort();
+        unfired
+    }
+
+    /// The latest any print, say or fire ran compared to its planned show
+    /// time, in seconds, and that show time.
+    pub fn latest(&self) -> Option<(f64, Duration)> {
+        self.latest
+    }
+
+    /// Run every remaining cue, until the show finishes or is aborted.
+    pub fn run<F: FnMut(ShowEvent)>(&mut self, mut on_event: F) -> ShowState {
+        while self.step(&mut on_event) == ShowState::Running {}
+        self.state
+    }
+
+    /// Run the next cue, waiting for its show time first.
+    pub fn step<F: FnMut(ShowEvent)>(&mut self, mut on_event: F) -> ShowState {
+        if self.state != ShowState::Running {
+            return self.state;
+        }
+        if self.clock.is_none() {
+            self.clock = Some(ShowClock::start());
+        }
+        if !self.poll(&mut on_event) {
+            return self.abort_with(&mut on_event);
+        }
+        if self.next == self.script.cues.len() {
+            self.state = ShowState::Finished;
+            return self.state;
+        }
+
+        let cue = self.script.cues[self.next].clone();
+        let line = self.script.lines[self.next].clone();
+        self.next += 1;
+        match cue {
+            Cue::Board { .. } | Cue::Channel { .. } | Cue::Group { .. } |
+            Cue::Threshold { .. } | Cue::Igniter { .. } => return self.state,
+            _ => {},
+        }
+
+        let lateness = self.lateness(self.show_time);
+        if let Cue::Print { .. } | Cue::Say { .. } = cue {
+            self.record_lateness(lateness, self.show_time);
+        }
+        on_event(ShowEvent::Cue { line, cue: cue.clone(), planned: self.show_time, lateness });
+
+        let carry_on = match cue {
+            Cue::Sleep { time } => {
+                self.show_time += time;
+                self.wait_until(self.show_time, &mut on_event)
+            },
+            Cue::At { time } => {
+                self.show_time = time;
+                self.wait_until(self.show_time, &mut on_event)
+            },
+            Cue::Pause => {
+                on_event(ShowEvent::Paused);
+                self.pause()
+            },
+            Cue::FireGap { time } => {
+                self.fire_gap = time;
+                true
+            },
+            Cue::Fire { ref channels } => self.fire(channels, &mut on_event),
+            _ => true,
+        };
+
+        if carry_on {
+            self.state
+        } else {
+            self.abort_with(&mut on_event)
+        }
+    }
+
+    /// Stop the show and disarm every board, returning the result for each.
+    pub fn abort(&mut self) -> Vec<(Board, PortfireResult<()>)> {
+        self.state = ShowState::Aborted;
+        self.session.disarm_all()
+    }
+
+    fn abort_with<F: FnMut(ShowEvent)>(&mut self, on_event: &mut F) -> ShowState {
+        let disarmed = self.abort();
+        on_event(ShowEvent::Aborted { disarmed });
+        self.state
+    }
+
+    /// Hold the show clock until `Command::Continue`. Returns false if the
+    /// show should abort, including when no more commands can arrive.
+    pub fn pause(&mut self) -> bool {
+        if let Some(ref mut clock) = self.clock {
+            clock.hold();
+        }
+        let resumed = loop {
+            match self.commands.recv() {
+                Ok(Command::Abort) | Err(_) => break false,
+                Ok(Command::Continue) | Ok(Command::Resume) => break true,
+                Ok(Command::Hold) => {},
+            }
+        };
+        if let Some(ref mut clock) = self.clock {
+            clock.resume();
+        }
+        resumed
+    }
+
+    /// Act on any commands sent since the last check. Returns false if the
+    /// show should abort.
+    fn poll<F: FnMut(ShowEvent)>(&mut self, on_event: &mut F) -> bool {
+        while let Ok(command) = self.commands.try_recv() {
+            if !self.handle(command, on_event) {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn handle<F: FnMut(ShowEvent)>(&mut self, command: Command, on_event: &mut F) -> bool {
+        match command {
+            Command::Abort => false,
+            Command::Hold => {
+                on_event(ShowEvent::Held);
+                let resumed = self.pause();
+                if resumed {
+                    on_event(ShowEvent::Resumed);
+                }
+                resumed
+            },
+            Command::Continue | Command::Resume => true,
+        }
+    }
+
+    /// Wait until the show clock reaches `time`, however long has been spent
+    /// on earlier cues. Returns false if the show should abort.
+    fn wait_until<F: FnMut(ShowEvent)>(&mut self, time: Duration, on_event: &mut F) -> bool {
+        if self.options.skip_sleep {
+            return true;
+        }
+        loop {
+            let remaining = self.clock.as_ref().map_or(Duration::from_secs(0), |c| c.until(time));
+            if remaining == Duration::from_secs(0) {
+                return true;
+            }
+            match self.commands.recv_timeout(remaining) {
+                Ok(command) => if !self.handle(command, on_event) {
+                    return false;
+                },
+                Err(RecvTimeoutError::Timeout) => {},
+                Err(RecvTimeoutError::Disconnected) => thread::sleep(remaining),
+            }
+        }
+    }
+
+    fn lateness(&self, time: Duration) -> f64 {
+        self.clock.as_ref().map_or(0.0, |c| c.lateness(time))
+    }
+
+    fn record_lateness(&mut self, lateness: f64, planned: Duration) {
+        if self.latest.is_none_or(|(l, _)| lateness > l) {
+            self.latest = Some((lateness, planned));
+        }
+    }
+
+    /// Fire at most three channels per board at once, waiting for the fire
+    /// gap between each step. Returns false if the show should abort.
+    fn fire<F: FnMut(ShowEvent)>(&mut self, channels: &[String], on_event: &mut F) -> bool {
+        for (idx, step) in self.script.fire_steps(channels).into_iter().enumerate() {
+            if idx > 0 {
+                self.show_time += self.fire_gap;
+                if !self.wait_until(self.show_time, on_event) {
+                    return false;
+                }
+            }
+            let lateness = self.lateness(self.show_time);
+            self.record_lateness(lateness, self.show_time);
+            on_event(ShowEvent::FireStep { planned: self.show_time, lateness,
+                                           boards: step.clone() });
+
+            if self.options.dry_run {
+                for (board_id, firing_chans) in step {
+                    let names = channel_names(&self.script, &board_id, firing_chans);
+                    self.fired.extend(names);
+                }
+                continue;
+            }
+
+            // Send the fire commands to every board found at once
+            let fires: Vec<(&Client, [u8; 3])> =
+                step.iter()
+                    .filter_map(|&(ref b, chans)| self.clients.get(b).map(|c| (&**c, chans)))
+                    .collect();
+            let boards = fires.len();
+            let report = portfire::fire_parallel(&fires, &self.options.retry_policy);
+            let mut outcomes = report.outcomes.into_iter();
+
+            for (board_id, firing_chans) in step.iter().cloned() {
+                let outcome = if self.clients.contains_key(&board_id) {
+                    outcomes.next().expect("an outcome for every board fired")
+                } else {
+                    let error = io::Error::new(io::ErrorKind::NotFound, "board not found");
+                    FireOutcome::Failed { attempts: 0, error: PortfireError::Connect(error) }
+                };
+                if outcome.fired() {
+                    let names = channel_names(&self.script, &board_id, firing_chans);
+                    self.fired.extend(names);
+                }
+                on_event(ShowEvent::Fired { board_id, channels: firing_chans, outcome });
+            }
+            match report.skew {
+                Some(skew) if boards > 1 => on_event(ShowEvent::Skew { boards, skew }),
+                _ => {},
+            }
+        }
+        true
+    }
+}
+
+/// The names of the channels given by their numbers on a board.
+fn channel_names(script: &Script, board_id: &str, nums: [u8; 3]) -> Vec<String> {
+    let mut names: Vec<(u8, String)> = script.channels.iter()
+        .filter(|&(_, (b, n))| b == board_id && nums.contains(n))
+        .map(|(name, &(_, n))| (n, name.clone()))
+        .collect();
+    names.sort();
+    names.into_iter().map(|(_, name)| name).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{match_boards, Command, Show, ShowEvent, ShowOptions, ShowState};
+    use portfire::Board;
+    use script::Script;
+    use simulator::SimBoard;
+    use std::collections::HashMap;
+    use std::net::Ipv4Addr;
+    use std::sync::Arc;
+    use std::sync::mpsc;
+
+    fn script() -> Script {
+        Script::from_string("
+board 001 00:00:00:00:01:11
+board 002 00:00:00:00:01:12
+channel a 001 1
+channel b 001 2
+channel c 001 3
+channel d 001 4
+channel e 002 5
+channel f 001 6
+firegap 10ms
+fire a e
+sleep 20ms
+fire b c d f
+print Done
+".to_string()).unwrap()
+    }
+
+    #[test]
+    fn matching() {
+        let found = Board { ip: Ipv4Addr::new(10, 0, 0, 1), mac: [0, 0, 0, 0, 1, 0x11] };
+        let other = Board { ip: Ipv4Addr::new(10, 0, 0, 2), mac: [0, 0, 0, 0, 1, 0x99] };
+        let (matched, missing) = match_boards(&script(), &[other, found.clone()]);
+        assert_eq!(matched.len(), 1);
+        assert_eq!(matched["001"], found);
+        assert_eq!(missing, vec!["002".to_string()]);
+    }
+
+    #[test]
+    fn run() {
+        let sim1 = SimBoard::start(Ipv4Addr::new(127, 0, 1, 17), [0, 0, 0, 0, 1, 0x11]).unwrap();
+        let sim2 = SimBoard::start(Ipv4Addr::new(127, 0, 1, 18), [0, 0, 0, 0, 1, 0x12]).unwrap();
+        let script = script();
+        let (matched, missing) = match_boards(&script, &[sim1.board(), sim2.board()]);
+        assert!(missing.is_empty());
+        let clients: HashMap<_, _> = matched.into_iter()
+                                            .map(|(id, b)| (id, Arc::new(b.client())))
+                                            .collect();
+
+        let (_tx, rx) = mpsc::channel();
+        let mut show = Show::new(script, clients, rx, ShowOptions::default());
+        for client in show.clients().values() {
+            client.arm().unwrap();
+        }
+
+        let mut steps = Vec::new();
+        let mut fired = 0;
+        let state = show.run(|event| match event {
+            ShowEvent::FireStep { planned, .. } => steps.push(planned.as_millis() as u64),
+            ShowEvent::Fired { ref outcome, .. } if outcome.fired() => fired += 1,
+            _ => {},
+        });
+        assert_eq!(state, ShowState::Finished);
+        assert_eq!(steps, vec![0, 20, 30]);
+        assert_eq!(fired, 4);
+        assert_eq!(sim1.fired_channels(), vec![1, 2, 3, 4, 6]);
+        assert_eq!(sim2.fired_channels(), vec![5]);
+        assert_eq!(show.fired(), ["a", "e", "b", "c", "d", "f"]);
+        assert!(show.unfired().is_empty());
+        assert!(show.latest().is_some());
+
+        drop(show);
+        assert!(!sim1.state().armed);
+        assert!(!sim2.state().armed);
+    }
+
+    #[test]
+    fn abort() {
+        let sim = SimBoard::start(Ipv4Addr::new(127, 0, 1, 19), [0, 0, 0, 0, 1, 0x11]).unwrap();
+        let mut clients = HashMap::new();
+        clients.insert("001".to_string(), Arc::new(sim.board().client()));
+
+        let (tx, rx) = mpsc::channel();
+        let options = ShowOptions { skip_sleep: true, ..ShowOptions::default() };
+        let mut show = Show::new(script(), clients, rx, options);
+        show.clients()["001"].arm().unwrap();
+
+        // Board 002 wasn't found, so its channel fails to fire.
+        let mut failed = Vec::new();
+        while show.fired().is_empty() {
+            let state = show.step(|event| if let ShowEvent::Fired { board_id, outcome, .. } = event {
+                if !outcome.fired() {
+                    failed.push(board_id);
+                }
+            });
+            assert_eq!(state, ShowState::Running);
+        }
+        assert_eq!(failed, ["002"]);
+        assert_eq!(show.fired(), ["a"]);
+
+        tx.send(Command::Abort).unwrap();
+        let mut disarmed = 0;
+        let state = show.step(|event| if let ShowEvent::Aborted { disarmed: d } = event {
+            disarmed = d.len();
+        });
+        assert_eq!(state, ShowState::Aborted);
+        assert_eq!(disarmed, 1);
+        assert!(!sim.state().armed);
+        assert_eq!(show.unfired(), ["b", "c", "d", "e", "f"]);
+        assert_eq!(show.step(|_| {}), ShowState::Aborted);
+    }
+
+    #[test]
+    fn pause() {
+        let script = Script::from_string("pause\nprint Go\npause\n".to_string()).unwrap();
+        let (tx, rx) = mpsc::channel();
+        let options = ShowOptions { dry_run: true, ..ShowOptions::default() };
+        let mut show = Show::new(script, HashMap::new(), rx, options);
+
+        // Continue from the first pause only.
+        let mut tx = Some(tx);
+        let mut paused = 0;
+        let state = show.run(|event| if let ShowEvent::Paused = event {
+            paused += 1;
+            if let Some(tx) = tx.take() {
+                tx.send(Command::Continue).unwrap();
+            }
+        });
+
+        // The second pause can never be continued once the sender is gone.
+        assert_eq!(paused, 2);
+        assert_eq!(state, ShowState::Aborted);
+    }
+}
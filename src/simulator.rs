@@ -0,0 +1,341 @@
+use std::io;
+use std::io::prelude::*;
+use std::thread;
+use std::net::{Ipv4Addr, SocketAddrV4, Shutdown, TcpListener, TcpStream, UdpSocket};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::Duration;
+
+use portfire::Board;
+
+pub use checks::OPEN;
+
+/// The simulated state of a Portfire board.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimState {
+    pub armed: bool,
+    pub disarmed_voltage: f32,
+    pub armed_voltage: f32,
+    /// Continuity for channels 1 to 30, followed by the continuity test
+    /// voltage in tenths of a volt.
+    pub continuities: [u8; 31],
+    /// Every fire command received while armed, in order.
+    pub fired: Vec<[u8; 3]>,
+    /// Number of TCP connections accepted so far.
+    pub connections: usize,
+    /// Number of upcoming commands to refuse, to simulate a flaky board.
+    pub refuse_next: usize,
+    /// Number of upcoming fire commands to carry out without responding, as
+    /// if the response had been lost on the network.
+    pub drop_next_fire: usize,
+    /// Whether the board ignores every command, as if it had hung.
+    pub silent: bool,
+}
+
+impl SimState {
+    fn new() -> SimState {
+        let mut continuities = [OPEN; 31];
+        continuities[30] = 50;
+        SimState {
+            armed: false,
+            disarmed_voltage: 0.0,
+            armed_voltage: 12.0,
+            continuities,
+            fired: Vec::new(),
+            connections: 0,
+            refuse_next: 0,
+            drop_next_fire: 0,
+            silent: false,
+        }
+    }
+
+    fn bus_voltage(&self) -> f32 {
+        if self.armed { self.armed_voltage } else { self.disarmed_voltage }
+    }
+
+    /// Handle one command, returning the response bytes, or None if the
+    /// command was not understood.
+    fn handle(&mut self, cmd: &[u8]) -> Option<Vec<u8>> {
+        if self.refuse_next > 0 {
+            self.refuse_next -= 1;
+            return Some(b"ER".to_vec());
+        }
+        match cmd[0] {
+            b'p' => Some(b"OK".to_vec()),
+            b'a' => {
+                self.armed = true;
+                Some(b"OK".to_vec())
+            },
+            b'd' => {
+                self.armed = false;
+                Some(b"OK".to_vec())
+            },
+            b'f' => {
+                if self.armed {
+                    self.fired.push([cmd[1], cmd[2], cmd[3]]);
+                    Some(b"OK".to_vec())
+                } else {
+                    Some(b"ER".to_vec())
+                }
+            },
+            b'b' => {
+                let mv = (self.bus_voltage() * 1000.0) as u16;
+                Some(vec![mv as u8, (mv >> 8) as u8])
+            },
+            b'c' => Some(self.continuities.to_vec()),
+            _ => None,
+        }
+    }
+}
+
+/// A command a simulated board carried out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SimEvent {
+    Armed,
+    Disarmed,
+    /// The channels fired, with 0 for an unused slot.
+    Fired([u8; 3]),
+}
+
+/// A software stand-in for a Portfire board.
+///
+/// The simulator listens for TCP commands on port 9090 of its IP address and
+/// sends "PORTFIRE" beacons over UDP so it can be found by
+/// `portfire::autodiscover`. On Linux any address in 127.0.0.0/8 may be used,
+/// so several simulated boards can run side by side on one machine.
+pub struct SimBoard {
+    pub ip: Ipv4Addr,
+    pub mac: [u8; 6],
+    state: Arc<Mutex<SimState>>,
+    streams: Arc<Mutex<Vec<TcpStream>>>,
+    events: Arc<Mutex<Vec<Sender<SimEvent>>>>,
+    running: Arc<AtomicBool>,
+}
+
+impl SimBoard {
+    /// Start a simulated board, beaconing to 127.0.0.1.
+    pub fn start(ip: Ipv4Addr, mac: [u8; 6]) -> io::Result<SimBoard> {
+        SimBoard::start_with_beacon(ip, mac, SocketAddrV4::new(Ipv4Addr::LOCALHOST, 9090))
+    }
+
+    /// Start a simulated board, sending discovery beacons to `beacon`.
+    pub fn start_with_beacon(ip: Ipv4Addr, mac: [u8; 6], beacon: SocketAddrV4)
+        -> io::Result<SimBoard>
+    {
+        let listener = TcpListener::bind((ip, 9090))?;
+        let socket = UdpSocket::bind((ip, 0))?;
+        socket.set_broadcast(true)?;
+
+        let sim = SimBoard {
+            ip,
+            mac,
+            state: Arc::new(Mutex::new(SimState::new())),
+            streams: Arc::new(Mutex::new(Vec::new())),
+            events: Arc::new(Mutex::new(Vec::new())),
+            running: Arc::new(AtomicBool::new(true)),
+        };
+
+        let state = sim.state.clone();
+        let streams = sim.streams.clone();
+        let events = sim.events.clone();
+        let running = sim.running.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if !running.load(Ordering::SeqCst) {
+                    break;
+                }
+                if let Ok(stream) = stream {
+                    state.lock().unwrap().connections += 1;
+                    if let Ok(s) = stream.try_clone() {
+                        streams.lock().unwrap().push(s);
+                    }
+                    let state = state.clone();
+                    let events = events.clone();
+                    thread::spawn(move || serve(stream, state, events));
+                }
+            }
+        });
+
+        let mut packet = Vec::with_capacity(18);
+        packet.extend_from_slice(b"PORTFIRE");
+        packet.extend_from_slice(&ip.octets());
+        packet.extend_from_slice(&mac);
+        let running = sim.running.clone();
+        thread::spawn(move || {
+            while running.load(Ordering::SeqCst) {
+                let _ = socket.send_to(&packet, beacon);
+                thread::sleep(Duration::from_millis(250));
+            }
+        });
+
+        Ok(sim)
+    }
+
+    /// A `portfire::Board` that talks to this simulator.
+    pub fn board(&self) -> Board {
+        Board { ip: self.ip, mac: self.mac }
+    }
+
+    /// Lock and access the simulated state, to inspect or modify it.
+    pub fn state(&self) -> MutexGuard<'_, SimState> {
+        self.state.lock().unwrap()
+    }
+
+    /// Receive every arm, disarm and fire command the board carries out from
+    /// now on, however quickly they follow each other.
+    pub fn events(&self) -> Receiver<SimEvent> {
+        let (tx, rx) = mpsc::channel();
+        self.events.lock().unwrap().push(tx);
+        rx
+    }
+
+    /// Set the continuity reading for a channel numbered 1 to 30.
+    pub fn set_continuity(&self, channel: u8, value: u8) {
+        self.state().continuities[channel as usize - 1] = value;
+    }
+
+    /// Close every open connection, as if the board had been power cycled
+    /// or the network had dropped out.
+    pub fn disconnect_all(&self) {
+        for stream in self.streams.lock().unwrap().drain(..) {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+    }
+
+    /// All channels fired so far, in order, excluding unused (0) slots.
+    pub fn fired_channels(&self) -> Vec<u8> {
+        self.state().fired.iter()
+                          .flat_map(|chans| chans.iter().cloned())
+                          .filter(|&ch| ch != 0)
+                          .collect()
+    }
+}
+
+impl Drop for SimBoard {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        // Wake the listener thread so it notices it should stop.
+        let _ = TcpStream::connect((self.ip, 9090));
+    }
+}
+
+/// Serve commands on one connection until the client hangs up.
+fn serve(mut stream: TcpStream, state: Arc<Mutex<SimState>>,
+         events: Arc<Mutex<Vec<Sender<SimEvent>>>>)
+{
+    let _ = stream.set_nodelay(true);
+    let mut cmd = [0u8; 4];
+    loop {
+        if stream.read_exact(&mut cmd[..1]).is_err() {
+            return;
+        }
+        if cmd[0] == b'f' && stream.read_exact(&mut cmd[1..]).is_err() {
+            return;
+        }
+        let response = {
+            let mut state = state.lock().unwrap();
+            if state.silent {
+                continue;
+            }
+            let response = state.handle(&cmd);
+            let event = match (cmd[0], response.as_ref()) {
+                (b'a', Some(r)) if r == b"OK" => Some(SimEvent::Armed),
+                (b'd', Some(r)) if r == b"OK" => Some(SimEvent::Disarmed),
+                (b'f', Some(r)) if r == b"OK" => Some(SimEvent::Fired([cmd[1], cmd[2], cmd[3]])),
+                _ => None,
+            };
+            // Sent while the state is locked, so events from several
+            // connections arrive in the order they happened.
+            if let Some(event) = event {
+                events.lock().unwrap().retain(|tx| tx.send(event).is_ok());
+            }
+            if cmd[0] == b'f' && state.drop_next_fire > 0 {
+                state.drop_next_fire -= 1;
+                continue;
+            }
+            response
+        };
+        match response {
+            Some(response) => if stream.write_all(&response).is_err() {
+                return;
+            },
+            None => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SimBoard, SimEvent, OPEN};
+    use portfire::{self, PortfireError};
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn ping_arm_disarm() {
+        let sim = SimBoard::start(Ipv4Addr::new(127, 0, 1, 1), [0, 0, 0, 0, 1, 1]).unwrap();
+        let board = sim.board();
+        board.ping().unwrap();
+        board.arm().unwrap();
+        assert!(sim.state().armed);
+        board.disarm().unwrap();
+        assert!(!sim.state().armed);
+    }
+
+    #[test]
+    fn bus_voltage() {
+        let sim = SimBoard::start(Ipv4Addr::new(127, 0, 1, 2), [0, 0, 0, 0, 1, 2]).unwrap();
+        let board = sim.board();
+        assert_eq!(board.bus_voltage().unwrap(), 0.0);
+        board.arm().unwrap();
+        assert_eq!(board.bus_voltage().unwrap(), 12.0);
+    }
+
+    #[test]
+    fn continuities() {
+        let sim = SimBoard::start(Ipv4Addr::new(127, 0, 1, 3), [0, 0, 0, 0, 1, 3]).unwrap();
+        sim.set_continuity(4, 2);
+        let conts = sim.board().continuities().unwrap();
+        assert_eq!(conts[3], 2);
+        assert_eq!(conts[0], OPEN);
+        assert_eq!(conts[30], 50);
+    }
+
+    #[test]
+    fn fire() {
+        let sim = SimBoard::start(Ipv4Addr::new(127, 0, 1, 4), [0, 0, 0, 0, 1, 4]).unwrap();
+        let board = sim.board();
+        match board.fire([1, 2, 0]) {
+            Err(PortfireError::Refused) => {},
+            r => panic!("expected Refused, got {:?}", r),
+        }
+        assert!(sim.fired_channels().is_empty());
+        board.arm().unwrap();
+        board.fire([1, 2, 0]).unwrap();
+        board.fire([7, 0, 0]).unwrap();
+        assert_eq!(sim.fired_channels(), vec![1, 2, 7]);
+    }
+
+    #[test]
+    fn autodiscover() {
+        let sim = SimBoard::start(Ipv4Addr::new(127, 0, 1, 5), [0, 0, 0, 0, 1, 5]).unwrap();
+        let boards = portfire::autodiscover().unwrap();
+        assert!(boards.contains(&sim.board()));
+    }
+
+    #[test]
+    fn events() {
+        let sim = SimBoard::start(Ipv4Addr::new(127, 0, 1, 26), [0, 0, 0, 0, 1, 26]).unwrap();
+        let events = sim.events();
+        let board = sim.board();
+        board.fire([1, 0, 0]).unwrap_err();
+        board.arm().unwrap();
+        board.fire([1, 2, 0]).unwrap();
+        board.disarm().unwrap();
+        board.arm().unwrap();
+        board.disarm().unwrap();
+        let events: Vec<SimEvent> = events.try_iter().collect();
+        assert_eq!(events, vec![SimEvent::Armed, SimEvent::Fired([1, 2, 0]), SimEvent::Disarmed,
+                                SimEvent::Armed, SimEvent::Disarmed]);
+    }
+}
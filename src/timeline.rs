@@ -0,0 +1,160 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use script::{Cue, Script, SourceLine, DEFAULT_FIRE_GAP_MS};
+
+/// A fire command sent to one board.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimelineFire {
+    pub time: Duration,
+    pub board_id: String,
+    /// Physical channel numbers on the board.
+    pub channels: Vec<u8>,
+}
+
+/// A cue and the show time it runs at.
+#[derive(Debug, PartialEq)]
+pub struct TimelineEntry<'a> {
+    pub time: Duration,
+    pub line: &'a SourceLine,
+    /// The number of pauses before this cue. Show time doesn't count time
+    /// spent paused, so once there has been a pause it's no longer known how
+    /// long after the start the cue will run.
+    pub pauses: usize,
+    pub cue: &'a Cue,
+    /// For fire cues, the commands sent to each board, which may be in
+    /// several steps for cues with more than three channels on one board.
+    pub fires: Vec<TimelineFire>,
+}
+
+/// How much a board is used over the whole script.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BoardFires {
+    pub commands: usize,
+    pub channels: usize,
+}
+
+/// The show time of every cue in a script, as computed when it was parsed.
+#[derive(Debug, PartialEq)]
+pub struct Timeline<'a> {
+    pub entries: Vec<TimelineEntry<'a>>,
+    /// Fire counts for every board in the script, including any never fired.
+    pub boards: BTreeMap<String, BoardFires>,
+}
+
+impl<'a> Timeline<'a> {
+    pub fn new(script: &'a Script) -> Timeline<'a> {
+        let mut boards: BTreeMap<String, BoardFires> =
+            script.boards.keys().map(|b| (b.clone(), BoardFires::default())).collect();
+        let mut entries = Vec::new();
+        let mut time = Duration::from_secs(0);
+        let mut fire_gap = Duration::from_millis(DEFAULT_FIRE_GAP_MS);
+        let mut pauses = 0;
+
+        for (cue, line) in script.cues.iter().zip(&script.lines) {
+            let start = match *cue {
+                Cue::At { time: at } => at,
+                _ => time,
+            };
+            let mut fires = Vec::new();
+            let pauses_before = pauses;
+
+            match *cue {
+                Cue::Sleep { time: sleep } => time += sleep,
+                Cue::At { time: at } => time = at,
+                Cue::FireGap { time: gap } => fire_gap = gap,
+                Cue::Pause => pauses += 1,
+                Cue::Fire { ref channels } => {
+                    for (idx, step) in script.fire_steps(channels).into_iter().enumerate() {
+                        if idx > 0 {
+                            time += fire_gap;
+                        }
+                        for (board_id, firing_chans) in step {
+                            let channels: Vec<u8> = firing_chans.iter()
+                                                                .cloned()
+                                                                .filter(|&c| c != 0)
+                                                                .collect();
+                            let counts = boards.entry(board_id.clone()).or_default();
+                            counts.commands += 1;
+                            counts.channels += channels.len();
+                            fires.push(TimelineFire { time, board_id, channels });
+                        }
+                    }
+                },
+                _ => {},
+            }
+
+            entries.push(TimelineEntry { time: start, line, pauses: pauses_before, cue, fires });
+        }
+
+        Timeline { entries, boards }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BoardFires, Timeline, TimelineFire};
+    use script::Script;
+    use std::time::Duration;
+
+    #[test]
+    fn timeline() {
+        let script = Script::from_string("
+board 001 00:00:00:00:00:01
+board 002 00:00:00:00:00:02
+board 003 00:00:00:00:00:03
+channel a 001 1
+channel b 001 2
+channel c 001 3
+channel d 001 4
+channel e 002 7
+sleep 1.5
+fire e
+pause
+firegap 250ms
+at 10 fire a b c d
+sleep 1
+".to_string()).unwrap();
+
+        let timeline = Timeline::new(&script);
+        let times: Vec<(usize, u64, usize)> = timeline.entries.iter()
+            .map(|e| (e.line.lineno, e.time.as_millis() as u64, e.pauses))
+            .collect();
+        assert_eq!(times[8..], [(10, 0, 0), (11, 1500, 0), (12, 1500, 0), (13, 1500, 1),
+                                (14, 10000, 1), (14, 10000, 1), (15, 10250, 1)]);
+
+        assert_eq!(timeline.entries[9].fires, vec![TimelineFire {
+            time: Duration::from_millis(1500), board_id: "002".to_string(), channels: vec![7]
+        }]);
+        let fires: Vec<(u64, Vec<u8>)> = timeline.entries[13].fires.iter()
+            .map(|f| (f.time.as_millis() as u64, f.channels.clone()))
+            .collect();
+        assert_eq!(fires, vec![(10000, vec![1, 2, 3]), (10250, vec![4])]);
+
+        assert_eq!(timeline.boards["001"], BoardFires { commands: 2, channels: 4 });
+        assert_eq!(timeline.boards["002"], BoardFires { commands: 1, channels: 1 });
+        assert_eq!(timeline.boards["003"], BoardFires { commands: 0, channels: 0 });
+    }
+
+    #[test]
+    fn settings() {
+        let script = Script::from_string("
+threshold armed 10
+igniter ematch 1 5
+board 001 00:00:00:00:00:01
+channel a 001 1 ematch
+sleep 2
+threshold continuity 2.5
+fire a
+".to_string()).unwrap();
+
+        let timeline = Timeline::new(&script);
+        let times: Vec<(usize, u64, usize)> = timeline.entries.iter()
+            .map(|e| (e.line.lineno, e.time.as_millis() as u64, e.pauses))
+            .collect();
+        assert_eq!(times, [(2, 0, 0), (3, 0, 0), (4, 0, 0), (5, 0, 0), (6, 0, 0),
+                           (7, 2000, 0), (8, 2000, 0)]);
+        assert!(timeline.entries[..6].iter().all(|e| e.fires.is_empty()));
+        assert_eq!(timeline.entries[6].fires.len(), 1);
+    }
+}
@@ -0,0 +1,72 @@
+//! Runs a script end to end against simulated boards, as `setup` and `show`
+//! would against real ones.
+
+extern crate iris;
+
+use iris::checks::{self, AfterChecks, Thresholds};
+use iris::script::Script;
+use iris::show::{self, Show, ShowEvent, ShowOptions, ShowState};
+use iris::simulator::SimBoard;
+
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+use std::sync::mpsc;
+
+#[test]
+fn check_arm_fire_disarm() {
+    let sim1 = SimBoard::start(Ipv4Addr::new(127, 0, 2, 1), [0, 0, 0, 0, 2, 1]).unwrap();
+    let sim2 = SimBoard::start(Ipv4Addr::new(127, 0, 2, 2), [0, 0, 0, 0, 2, 2]).unwrap();
+    for &(sim, channel) in &[(&sim1, 1), (&sim1, 2), (&sim1, 3), (&sim1, 4), (&sim2, 7)] {
+        sim.set_continuity(channel, 3);
+    }
+
+    let script = Script::from_string("
+threshold armed 10
+igniter ematch 1 5
+board 001 00:00:00:00:02:01
+board 002 00:00:00:00:02:02
+channel a 001 1 ematch
+channel b 001 2 ematch
+channel c 001 3
+channel d 001 4
+channel e 002 7
+group finale b c d e
+firegap 10ms
+fire a
+sleep 50ms
+fire finale
+".to_string()).unwrap();
+
+    let (matched, missing) = show::match_boards(&script, &[sim1.board(), sim2.board()]);
+    assert!(missing.is_empty());
+    let clients: HashMap<_, _> = matched.into_iter()
+                                        .map(|(id, b)| (id, Arc::new(b.client())))
+                                        .collect();
+
+    let thresholds = Thresholds::from_script(&script);
+    let report = checks::check_boards(&script, &clients, &thresholds, AfterChecks::StayArmed);
+    assert!(report.passed(), "{:?}", report);
+    assert!(sim1.state().armed);
+    assert!(sim2.state().armed);
+
+    let (_tx, rx) = mpsc::channel();
+    let mut show = Show::new(script, clients, rx, ShowOptions::default());
+    let mut failed = Vec::new();
+    let state = show.run(|event| if let ShowEvent::Fired { board_id, outcome, .. } = event {
+        if !outcome.fired() {
+            failed.push(board_id);
+        }
+    });
+    assert_eq!(state, ShowState::Finished);
+    assert!(failed.is_empty());
+    assert_eq!(show.fired(), ["a", "b", "c", "d", "e"]);
+    assert!(show.unfired().is_empty());
+
+    assert_eq!(sim1.state().fired, vec![[1, 0, 0], [2, 3, 4]]);
+    assert_eq!(sim2.state().fired, vec![[7, 0, 0]]);
+
+    drop(show);
+    assert!(!sim1.state().armed);
+    assert!(!sim2.state().armed);
+}